@@ -3,9 +3,10 @@ use crate::specs::{
     Nibble,
     Byte,
     Address,
+    Platform,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InstructionData {
     Sys(Address),
     Cls,
@@ -42,9 +43,42 @@ pub enum InstructionData {
     LdB(Nibble),
     LdIMem(Nibble),
     LdVx(Nibble),
+    Scd(Nibble),
+    Scr,
+    Scl,
+    Exit,
+    Low,
+    High,
+    LdHf(Nibble),
+    LdR(Nibble),
+    LdVxR(Nibble),
+    Scu(Nibble),
+    SaveRange(Nibble, Nibble),
+    LoadRange(Nibble, Nibble),
+    LdILong,
+    Plane(Nibble),
+    Audio,
+    LdPitch(Nibble),
     Unknown,
 }
 
+impl InstructionData {
+    // The first platform to support the instruction.
+    pub fn platform(&self) -> Platform {
+        use InstructionData::*;
+
+        match self {
+            Scd(_) | Scr | Scl | Exit | Low | High | Drw(_, _, 0) | LdHf(_) | LdR(_) | LdVxR(_) => {
+                Platform::SuperChip
+            },
+            Scu(_) | SaveRange(_, _) | LoadRange(_, _) | LdILong | Plane(_) | Audio | LdPitch(_) => {
+                Platform::XoChip
+            },
+            _ => Platform::Chip8,
+        }
+    }
+}
+
 pub fn decode_instruction(instruction: Instruction) -> InstructionData {
     let x = ((instruction & 0x0F00) >> 8) as Nibble;
     let y = ((instruction & 0x00F0) >> 4) as Nibble;
@@ -55,7 +89,14 @@ pub fn decode_instruction(instruction: Instruction) -> InstructionData {
             match n {
                 0x0E0 => InstructionData::Cls,
                 0x0EE => InstructionData::Ret,
+                0x0FB => InstructionData::Scr,
+                0x0FC => InstructionData::Scl,
+                0x0FD => InstructionData::Exit,
+                0x0FE => InstructionData::Low,
+                0x0FF => InstructionData::High,
                 0x000 => InstructionData::Unknown,
+                n if n & 0xFF0 == 0x0C0 => InstructionData::Scd(truncate_1_byte(n)),
+                n if n & 0xFF0 == 0x0D0 => InstructionData::Scu(truncate_1_byte(n)),
                 n => InstructionData::Sys(n),
             }
         },
@@ -67,6 +108,8 @@ pub fn decode_instruction(instruction: Instruction) -> InstructionData {
         0x5 => {
             match n & 0x000F {
                 0x0 => InstructionData::SeReg(x, y),
+                0x2 => InstructionData::SaveRange(x, y),
+                0x3 => InstructionData::LoadRange(x, y),
                 _ => InstructionData::Unknown,
             }
         },
@@ -105,15 +148,22 @@ pub fn decode_instruction(instruction: Instruction) -> InstructionData {
         },
         0xF => {
             match n & 0x00FF {
+                0x00 if x == 0 => InstructionData::LdILong,
+                0x01 => InstructionData::Plane(x),
+                0x02 if x == 0 => InstructionData::Audio,
                 0x07 => InstructionData::LdRegDt(x),
                 0x0A => InstructionData::LdK(x),
                 0x15 => InstructionData::LdDtReg(x),
                 0x18 => InstructionData::LdSt(x),
                 0x1E => InstructionData::AddI(x),
                 0x29 => InstructionData::LdF(x),
+                0x30 => InstructionData::LdHf(x),
+                0x3A => InstructionData::LdPitch(x),
                 0x33 => InstructionData::LdB(x),
                 0x55 => InstructionData::LdIMem(x),
                 0x65 => InstructionData::LdVx(x),
+                0x75 => InstructionData::LdR(x),
+                0x85 => InstructionData::LdVxR(x),
                 _ => InstructionData::Unknown,
             }
         },
@@ -121,6 +171,69 @@ pub fn decode_instruction(instruction: Instruction) -> InstructionData {
     }
 }
 
+// The opcode of an instruction, `Unknown` has none and becomes 0x0000.
+pub fn encode_instruction(data: InstructionData) -> Instruction {
+    use InstructionData::*;
+
+    let xy = |base: Instruction, x: Nibble, y: Nibble| base | (x as Instruction) << 8 | (y as Instruction) << 4;
+    let xn = |base: Instruction, x: Nibble, n: Byte| base | (x as Instruction) << 8 | n as Instruction;
+
+    match data {
+        Sys(n) => n & 0x0FFF,
+        Cls => 0x00E0,
+        Ret => 0x00EE,
+        Jp(n) => 0x1000 | (n & 0x0FFF),
+        Call(n) => 0x2000 | (n & 0x0FFF),
+        Se(x, n) => xn(0x3000, x, n),
+        Sne(x, n) => xn(0x4000, x, n),
+        SeReg(x, y) => xy(0x5000, x, y),
+        Ld(x, n) => xn(0x6000, x, n),
+        Add(x, n) => xn(0x7000, x, n),
+        LdReg(x, y) => xy(0x8000, x, y),
+        Or(x, y) => xy(0x8001, x, y),
+        And(x, y) => xy(0x8002, x, y),
+        Xor(x, y) => xy(0x8003, x, y),
+        AddReg(x, y) => xy(0x8004, x, y),
+        SubReg(x, y) => xy(0x8005, x, y),
+        Shr(x, y) => xy(0x8006, x, y),
+        SubN(x, y) => xy(0x8007, x, y),
+        Shl(x, y) => xy(0x800E, x, y),
+        SneReg(x, y) => xy(0x9000, x, y),
+        LdI(n) => 0xA000 | (n & 0x0FFF),
+        JpV0(n) => 0xB000 | (n & 0x0FFF),
+        Rnd(x, n) => xn(0xC000, x, n),
+        Drw(x, y, n) => xy(0xD000, x, y) | n as Instruction,
+        Skp(x) => xn(0xE09E, x, 0),
+        Sknp(x) => xn(0xE0A1, x, 0),
+        LdRegDt(x) => xn(0xF007, x, 0),
+        LdK(x) => xn(0xF00A, x, 0),
+        LdDtReg(x) => xn(0xF015, x, 0),
+        LdSt(x) => xn(0xF018, x, 0),
+        AddI(x) => xn(0xF01E, x, 0),
+        LdF(x) => xn(0xF029, x, 0),
+        LdB(x) => xn(0xF033, x, 0),
+        LdIMem(x) => xn(0xF055, x, 0),
+        LdVx(x) => xn(0xF065, x, 0),
+        Scd(n) => 0x00C0 | n as Instruction,
+        Scr => 0x00FB,
+        Scl => 0x00FC,
+        Exit => 0x00FD,
+        Low => 0x00FE,
+        High => 0x00FF,
+        LdHf(x) => xn(0xF030, x, 0),
+        LdR(x) => xn(0xF075, x, 0),
+        LdVxR(x) => xn(0xF085, x, 0),
+        Scu(n) => 0x00D0 | n as Instruction,
+        SaveRange(x, y) => xy(0x5002, x, y),
+        LoadRange(x, y) => xy(0x5003, x, y),
+        LdILong => 0xF000,
+        Plane(n) => xn(0xF001, n, 0),
+        Audio => 0xF002,
+        LdPitch(x) => xn(0xF03A, x, 0),
+        Unknown => 0x0000,
+    }
+}
+
 impl std::fmt::Display for InstructionData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use InstructionData::*;
@@ -161,6 +274,22 @@ impl std::fmt::Display for InstructionData {
             LdB(x) => write!(f, "{:<5} B, V{:1X}", "LD", x),
             LdIMem(x) => write!(f, "{:<5} [I], V{:1X}", "LD", x),
             LdVx(x) => write!(f, "{:<5} V{:1X}, [I]", "LD", x),
+            Scd(n) => write!(f, "{:<5} {:#03X}", "SCD", n),
+            Scr => write!(f, "{:<5}", "SCR"),
+            Scl => write!(f, "{:<5}", "SCL"),
+            Exit => write!(f, "{:<5}", "EXIT"),
+            Low => write!(f, "{:<5}", "LOW"),
+            High => write!(f, "{:<5}", "HIGH"),
+            LdHf(x) => write!(f, "{:<5} HF, V{:1X}", "LD", x),
+            LdR(x) => write!(f, "{:<5} R, V{:1X}", "LD", x),
+            LdVxR(x) => write!(f, "{:<5} V{:1X}, R", "LD", x),
+            Scu(n) => write!(f, "{:<5} {:#03X}", "SCU", n),
+            SaveRange(x, y) => write!(f, "{:<5} V{:1X} - V{:1X}", "SAVE", x, y),
+            LoadRange(x, y) => write!(f, "{:<5} V{:1X} - V{:1X}", "LOAD", x, y),
+            LdILong => write!(f, "{:<5} I, LONG", "LD"),
+            Plane(n) => write!(f, "{:<5} {:1X}", "PLANE", n),
+            Audio => write!(f, "AUDIO"),
+            LdPitch(x) => write!(f, "{:<5} PITCH, V{:1X}", "LD", x),
             _ => write!(f, "XXXXXXXXXXXXXX")
         }
     }
@@ -182,7 +311,7 @@ mod tests {
     fn decode_simple() {
         match decode_instruction(0x00E0) {
             InstructionData::Cls => {},
-            _ => assert!(false, "Decoded instruction is not CLS")
+            _ => panic!("Decoded instruction is not CLS")
         }
     }
 
@@ -191,7 +320,63 @@ mod tests {
         if let InstructionData::Sys(data) = decode_instruction(0x0314) {
             assert_eq!(data, 0x314);
         } else {
-            assert!(false, "Decoded instruction is not SYS");
+            panic!("Decoded instruction is not SYS");
+        }
+    }
+
+    #[test]
+    fn decode_superchip() {
+        match decode_instruction(0x00C4) {
+            InstructionData::Scd(4) => {},
+            _ => panic!("Decoded instruction is not SCD"),
+        }
+        match decode_instruction(0x00FF) {
+            InstructionData::High => {},
+            _ => panic!("Decoded instruction is not HIGH"),
+        }
+        match decode_instruction(0xF330) {
+            InstructionData::LdHf(3) => {},
+            _ => panic!("Decoded instruction is not LD HF"),
+        }
+    }
+
+    #[test]
+    fn decode_xochip() {
+        match decode_instruction(0xF000) {
+            InstructionData::LdILong => {},
+            _ => panic!("Decoded instruction is not LD I, LONG"),
+        }
+        match decode_instruction(0xF201) {
+            InstructionData::Plane(2) => {},
+            _ => panic!("Decoded instruction is not PLANE"),
+        }
+        match decode_instruction(0x5372) {
+            InstructionData::SaveRange(3, 7) => {},
+            _ => panic!("Decoded instruction is not SAVE"),
+        }
+        match decode_instruction(0xF002) {
+            InstructionData::Audio => {},
+            _ => panic!("Decoded instruction is not AUDIO"),
+        }
+        match decode_instruction(0xF53A) {
+            InstructionData::LdPitch(5) => {},
+            _ => panic!("Decoded instruction is not LD PITCH"),
+        }
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let opcodes = [
+            0x0314, 0x00E0, 0x00EE, 0x1234, 0x2345, 0x3456, 0x4567, 0x5670, 0x6789, 0x789A,
+            0x89A0, 0x89A1, 0x89A2, 0x89A3, 0x89A4, 0x89A5, 0x89A6, 0x89A7, 0x89AE, 0x9AB0,
+            0xA123, 0xB234, 0xC345, 0xD456, 0xE59E, 0xE6A1, 0xF707, 0xF80A, 0xF915, 0xFA18,
+            0xFB1E, 0xFC29, 0xFD33, 0xFE55, 0xFF65, 0x00C4, 0x00FB, 0x00FC, 0x00FD, 0x00FE,
+            0x00FF, 0xF130, 0xF275, 0xF385, 0x00D5, 0x5372, 0x5373, 0xF000, 0xF201, 0xF002,
+            0xF53A,
+        ];
+
+        for &opcode in opcodes.iter() {
+            assert_eq!(encode_instruction(decode_instruction(opcode)), opcode, "{:#06X}", opcode);
         }
     }
 
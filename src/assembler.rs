@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::asm::{encode_instruction, InstructionData};
+use crate::expr::parse_number;
+use crate::lines::LineTable;
+use crate::specs::{Address, Byte, Nibble, PROGRAM_BEGIN, XO_MEMORY_SIZE};
+
+pub mod error {
+    use std::fmt;
+    use std::io;
+
+    #[derive(Debug)]
+    pub enum AssemblerError {
+        IOError(io::Error),
+        ParseError(usize, String),
+    }
+
+    impl From<io::Error> for AssemblerError {
+        fn from(err: io::Error) -> Self {
+            AssemblerError::IOError(err)
+        }
+    }
+
+    impl fmt::Display for AssemblerError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                AssemblerError::IOError(err) => write!(f, "I/O error: {}", err),
+                AssemblerError::ParseError(line, msg) => write!(f, "source line {}: {}", line, msg),
+            }
+        }
+    }
+}
+
+use error::AssemblerError;
+
+static MNEMONICS: [&str; 35] = [
+    "SYS", "CLS", "RET", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+    "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SCU",
+    "SAVE", "LOAD", "PLANE", "AUDIO", "DB", "DW", "BYTE", "WORD",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Register(Nibble),
+    // A number or a label, resolved once all labels are known.
+    Value(String),
+    I,
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    B,
+    HF,
+    R,
+    Long,
+    Pitch,
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let upper = text.to_uppercase();
+
+        Ok(match upper.as_str() {
+            "" => return Err("missing operand".to_owned()),
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::DT,
+            "ST" => Operand::ST,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "B" => Operand::B,
+            "HF" => Operand::HF,
+            "R" => Operand::R,
+            "LONG" => Operand::Long,
+            "PITCH" => Operand::Pitch,
+            register if register.len() == 2 && register.starts_with('V') => {
+                match Nibble::from_str_radix(&register[1..], 16) {
+                    Ok(index) => Operand::Register(index),
+                    Err(_) => Operand::Value(text.to_owned()),
+                }
+            },
+            _ => Operand::Value(text.to_owned()),
+        })
+    }
+}
+
+struct Statement {
+    line: usize,
+    address: usize,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+// An assembled ROM, with the source line of each instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub bytes: Vec<Byte>,
+    pub lines: LineTable,
+}
+
+impl Program {
+    pub fn save(&self, rom: &Path, lines: Option<&Path>) -> Result<(), AssemblerError> {
+        fs::write(rom, &self.bytes)?;
+        if let Some(lines) = lines {
+            fs::write(lines, self.lines.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|chr| chr.is_ascii_alphabetic() || chr == '_')
+        && chars.all(|chr| chr.is_ascii_alphanumeric() || chr == '_')
+}
+
+fn resolve(text: &str, labels: &HashMap<String, usize>, max: usize) -> Result<usize, String> {
+    let value = match labels.get(text) {
+        Some(&address) => address as i64,
+        None if text.starts_with(|chr: char| chr.is_ascii_digit()) => parse_number(text)?,
+        None => return Err(format!("unknown label: {}", text)),
+    };
+
+    if value < 0 || value as usize > max {
+        return Err(format!("value out of range: {}", text));
+    }
+    Ok(value as usize)
+}
+
+fn instruction(mnemonic: &str, operands: &[Operand], labels: &HashMap<String, usize>)
+    -> Result<InstructionData, String> {
+    use InstructionData::*;
+    use Operand::{Register, Value};
+
+    let address = |text: &str| resolve(text, labels, 0xFFF).map(|value| value as Address);
+    let byte = |text: &str| resolve(text, labels, 0xFF).map(|value| value as Byte);
+    let nibble = |text: &str| resolve(text, labels, 0xF).map(|value| value as Nibble);
+
+    Ok(match (mnemonic, operands) {
+        ("SYS", [Value(n)]) => Sys(address(n)?),
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("JP", [Value(n)]) => Jp(address(n)?),
+        ("JP", [Register(0), Value(n)]) => JpV0(address(n)?),
+        ("CALL", [Value(n)]) => Call(address(n)?),
+        ("SE", [Register(x), Value(n)]) => Se(*x, byte(n)?),
+        ("SE", [Register(x), Register(y)]) => SeReg(*x, *y),
+        ("SNE", [Register(x), Value(n)]) => Sne(*x, byte(n)?),
+        ("SNE", [Register(x), Register(y)]) => SneReg(*x, *y),
+        ("LD", [Register(x), Value(n)]) => Ld(*x, byte(n)?),
+        ("LD", [Register(x), Register(y)]) => LdReg(*x, *y),
+        ("LD", [Operand::I, Value(n)]) => LdI(address(n)?),
+        ("LD", [Operand::I, Operand::Long]) => LdILong,
+        ("LD", [Register(x), Operand::DT]) => LdRegDt(*x),
+        ("LD", [Register(x), Operand::K]) => LdK(*x),
+        ("LD", [Operand::DT, Register(x)]) => LdDtReg(*x),
+        ("LD", [Operand::ST, Register(x)]) => LdSt(*x),
+        ("LD", [Operand::F, Register(x)]) => LdF(*x),
+        ("LD", [Operand::B, Register(x)]) => LdB(*x),
+        ("LD", [Operand::IndirectI, Register(x)]) => LdIMem(*x),
+        ("LD", [Register(x), Operand::IndirectI]) => LdVx(*x),
+        ("LD", [Operand::HF, Register(x)]) => LdHf(*x),
+        ("LD", [Operand::R, Register(x)]) => LdR(*x),
+        ("LD", [Register(x), Operand::R]) => LdVxR(*x),
+        ("LD", [Operand::Pitch, Register(x)]) => LdPitch(*x),
+        ("ADD", [Register(x), Value(n)]) => Add(*x, byte(n)?),
+        ("ADD", [Register(x), Register(y)]) => AddReg(*x, *y),
+        ("ADD", [Operand::I, Register(x)]) => AddI(*x),
+        ("OR", [Register(x), Register(y)]) => Or(*x, *y),
+        ("AND", [Register(x), Register(y)]) => And(*x, *y),
+        ("XOR", [Register(x), Register(y)]) => Xor(*x, *y),
+        ("SUB", [Register(x), Register(y)]) => SubReg(*x, *y),
+        ("SHR", [Register(x), Register(y)]) => Shr(*x, *y),
+        ("SUBN", [Register(x), Register(y)]) => SubN(*x, *y),
+        ("SHL", [Register(x), Register(y)]) => Shl(*x, *y),
+        ("RND", [Register(x), Value(n)]) => Rnd(*x, byte(n)?),
+        ("DRW", [Register(x), Register(y), Value(n)]) => Drw(*x, *y, nibble(n)?),
+        ("SKP", [Register(x)]) => Skp(*x),
+        ("SKNP", [Register(x)]) => Sknp(*x),
+        ("SCD", [Value(n)]) => Scd(nibble(n)?),
+        ("SCR", []) => Scr,
+        ("SCL", []) => Scl,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => Low,
+        ("HIGH", []) => High,
+        ("SCU", [Value(n)]) => Scu(nibble(n)?),
+        ("SAVE", [Register(x), Register(y)]) => SaveRange(*x, *y),
+        ("LOAD", [Register(x), Register(y)]) => LoadRange(*x, *y),
+        ("PLANE", [Value(n)]) => Plane(nibble(n)?),
+        ("AUDIO", []) => Audio,
+        _ if MNEMONICS.contains(&mnemonic) => return Err(format!("invalid operands for {}", mnemonic)),
+        _ => return Err(format!("unknown instruction: {}", mnemonic)),
+    })
+}
+
+// Sources hold one instruction per line, written the way the disassembler
+// prints them. `name:` labels the next address, `DB` and `DW` emit bytes
+// and big-endian words, and `;` starts a comment. The line table refers to
+// the source as `file`.
+pub fn assemble(source: &str, file: &str) -> Result<Program, AssemblerError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = PROGRAM_BEGIN;
+
+    for (idx, text) in source.lines().enumerate() {
+        let line = idx + 1;
+        let error = |msg: String| AssemblerError::ParseError(line, msg);
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(error(format!("invalid label: {}", label)));
+            }
+            // Operands with these names are read as registers or keywords.
+            if !matches!(Operand::parse(label), Ok(Operand::Value(_))) {
+                return Err(error(format!("reserved name cannot be a label: {}", label)));
+            }
+            if labels.insert(label.to_owned(), address).is_some() {
+                return Err(error(format!("duplicate label: {}", label)));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mnemonic = mnemonic.to_uppercase();
+        // The disassembler writes register ranges as `SAVE V1 - V3`.
+        let separator = if mnemonic == "SAVE" || mnemonic == "LOAD" { '-' } else { ',' };
+        let operands = match rest.trim() {
+            "" => Vec::new(),
+            rest => rest.split(separator).map(|operand| Operand::parse(operand.trim()))
+                        .collect::<Result<_, _>>().map_err(error)?,
+        };
+
+        let size = match mnemonic.as_str() {
+            "DB" | "BYTE" => operands.len(),
+            "DW" | "WORD" => operands.len() * 2,
+            _ => 2,
+        };
+        statements.push(Statement { line, address, mnemonic, operands });
+
+        address += size;
+        if address > XO_MEMORY_SIZE {
+            return Err(error("program does not fit in memory".to_owned()));
+        }
+    }
+
+    let mut program = Program { bytes: Vec::new(), lines: LineTable::new() };
+    for statement in statements {
+        let error = |msg: String| AssemblerError::ParseError(statement.line, msg);
+        let values = || statement.operands.iter().map(|operand| match operand {
+            Operand::Value(text) => Ok(text.as_str()),
+            _ => Err(error(format!("invalid operands for {}", statement.mnemonic))),
+        });
+
+        match statement.mnemonic.as_str() {
+            "DB" | "BYTE" => for text in values() {
+                program.bytes.push(resolve(text?, &labels, 0xFF).map_err(error)? as Byte);
+            },
+            "DW" | "WORD" => for text in values() {
+                let word = resolve(text?, &labels, 0xFFFF).map_err(error)? as u16;
+                program.bytes.extend_from_slice(&word.to_be_bytes());
+            },
+            mnemonic => {
+                let data = instruction(mnemonic, &statement.operands, &labels).map_err(error)?;
+                program.bytes.extend_from_slice(&encode_instruction(data).to_be_bytes());
+                program.lines.insert(statement.address as Address, file, statement.line);
+            },
+        }
+    }
+
+    Ok(program)
+}
+
+// Sources are named relative to the line table, which is how the debugger
+// finds them.
+pub fn assemble_file(path: &Path, lines: Option<&Path>) -> Result<Program, AssemblerError> {
+    let source = fs::read_to_string(path)?;
+    let file = lines.and_then(Path::parent)
+                    .and_then(|base| path.strip_prefix(base).ok())
+                    .unwrap_or(path);
+
+    assemble(&source, &file.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::decode_instruction;
+
+    #[test]
+    fn test_assemble() {
+        let source = "\
+; Draws a sprite forever.
+start:  LD    I, sprite
+        ld    v1, 0x10
+loop:   DRW   V0, V1, 2  ; two rows
+        SAVE  V0 - V1
+        JP    loop
+sprite: DB    0xF0, 144
+        DW    start
+";
+        let program = assemble(source, "game.s").unwrap();
+
+        assert_eq!(program.bytes, vec![0xA2, 0x0A, 0x61, 0x10, 0xD0, 0x12, 0x50, 0x12, 0x12, 0x04,
+                                       0xF0, 0x90, 0x02, 0x00]);
+        assert_eq!(program.lines.lookup(0x204).map(|source| source.line), Some(4));
+        assert_eq!(program.lines.lookup(0x20A), None);
+        assert_eq!(program.lines.address_of("game.s", 1), Some((0x200, 2)));
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        for &opcode in [0x00E0, 0x3A42, 0x8AB5, 0xD123, 0xF365, 0x00C4, 0x5373, 0xF000].iter() {
+            let text = decode_instruction(opcode).to_string();
+            let program = assemble(&text, "dis.s").unwrap();
+            assert_eq!(program.bytes, opcode.to_be_bytes().to_vec(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let line = |source: &str| match assemble(source, "game.s") {
+            Err(AssemblerError::ParseError(line, _)) => line,
+            _ => panic!("{:?} assembled", source),
+        };
+
+        assert_eq!(line("CLS\nFOO V1"), 2);
+        assert_eq!(line("LD V1"), 1);
+        assert_eq!(line("CLS\n\nJP nowhere"), 3);
+        assert_eq!(line("a: CLS\na: CLS"), 2);
+        assert_eq!(line("ADD V1, 0x100"), 1);
+        assert_eq!(line("DRW V1, V2, 16"), 1);
+        assert_eq!(line("DB V1"), 1);
+        assert_eq!(line("CLS\nb: CLS"), 2);
+        assert_eq!(line("vF: CLS"), 1);
+        assert_eq!(line("dt: CLS"), 1);
+        assert!(matches!(assemble("va: CLS", "game.s"),
+                         Err(AssemblerError::ParseError(1, msg)) if msg == "reserved name cannot be a label: va"));
+        assert!(assemble("vx: CLS\nbeep: JP vx", "game.s").is_ok());
+    }
+
+    #[test]
+    fn test_assemble_file() {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, rom, lines) = (dir.join("game.s"), dir.join("game.ch8"), dir.join("game.lines"));
+        fs::write(&source, "main: CALL draw\n      JP main\n\ndraw: CLS\n      RET\n").unwrap();
+
+        let program = assemble_file(&source, Some(&lines)).unwrap();
+        program.save(&rom, Some(&lines)).unwrap();
+        let table = LineTable::load(&lines);
+        let bytes = fs::read(&rom);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let table = table.unwrap();
+        assert_eq!(table, program.lines);
+        assert_eq!(table.files().into_iter().collect::<Vec<_>>(), vec!["game.s"]);
+        assert_eq!(table.address_of("game.s", 3), Some((0x204, 4)));
+        assert_eq!(bytes.unwrap(), vec![0x22, 0x04, 0x12, 0x00, 0x00, 0xE0, 0x00, 0xEE]);
+    }
+}
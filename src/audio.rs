@@ -0,0 +1,355 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::specs::{Byte, AUDIO_PATTERN_SIZE, DEFAULT_PITCH, FRAME_RATE};
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+    fn queue(&mut self, samples: &[f32]);
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tone {
+    pub frequency: f32,
+    pub volume: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+// An XO-CHIP sound: a 128 bit waveform looped at a rate driven by the pitch
+// register, 4000 bits per second at the default pitch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pattern {
+    pub bits: [Byte; AUDIO_PATTERN_SIZE],
+    pub pitch: Byte,
+}
+
+impl Pattern {
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    fn bit(&self, phase: f32) -> bool {
+        let index = (phase * (AUDIO_PATTERN_SIZE * 8) as f32) as usize % (AUDIO_PATTERN_SIZE * 8);
+        self.bits[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+}
+
+pub struct Beeper {
+    tone: Tone,
+    phase: f32,
+    remainder: u32,
+    samples: Vec<f32>,
+    sink: Box<dyn AudioSink>,
+}
+
+impl Beeper {
+    pub fn new(tone: Tone, sink: Box<dyn AudioSink>) -> Self {
+        Beeper {
+            tone,
+            phase: 0.0,
+            remainder: 0,
+            samples: Vec::new(),
+            sink,
+        }
+    }
+
+    fn frame_length(&mut self) -> usize {
+        let total = self.sink.sample_rate() + self.remainder;
+
+        self.remainder = total % FRAME_RATE;
+        (total / FRAME_RATE) as usize
+    }
+
+    pub fn render_frame(&mut self, active: bool, pattern: Option<&Pattern>) {
+        let length = self.frame_length();
+        let sample_rate = self.sink.sample_rate() as f32;
+        let step = match pattern {
+            Some(pattern) => pattern.playback_rate() / (AUDIO_PATTERN_SIZE * 8) as f32 / sample_rate,
+            None => self.tone.frequency / sample_rate,
+        };
+
+        self.samples.clear();
+        for _ in 0..length {
+            if active {
+                let high = match pattern {
+                    Some(pattern) => pattern.bit(self.phase),
+                    None => self.phase < 0.5,
+                };
+                let level = if high { 1.0 } else { -1.0 };
+                self.samples.push(level * self.tone.volume);
+                self.phase = (self.phase + step).fract();
+            } else {
+                self.samples.push(0.0);
+                self.phase = 0.0;
+            }
+        }
+
+        self.sink.queue(&self.samples);
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.sink.finish()
+    }
+}
+
+pub struct WavWriter<W: Write + Seek> {
+    output: W,
+    sample_rate: u32,
+    data_size: u32,
+    // The first failed write, reported by `finish`.
+    error: Option<io::Error>,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut output: W, sample_rate: u32) -> io::Result<Self> {
+        WavWriter::write_header(&mut output, sample_rate, 0)?;
+
+        Ok(WavWriter {
+            output,
+            sample_rate,
+            data_size: 0,
+            error: None,
+        })
+    }
+
+    fn write_header(output: &mut W, sample_rate: u32, data_size: u32) -> io::Result<()> {
+        output.write_all(b"RIFF")?;
+        output.write_all(&(36 + data_size).to_le_bytes())?;
+        output.write_all(b"WAVE")?;
+
+        output.write_all(b"fmt ")?;
+        output.write_all(&16u32.to_le_bytes())?;
+        output.write_all(&1u16.to_le_bytes())?;
+        output.write_all(&1u16.to_le_bytes())?;
+        output.write_all(&sample_rate.to_le_bytes())?;
+        output.write_all(&(sample_rate * 2).to_le_bytes())?;
+        output.write_all(&2u16.to_le_bytes())?;
+        output.write_all(&16u16.to_le_bytes())?;
+
+        output.write_all(b"data")?;
+        output.write_all(&data_size.to_le_bytes())
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavWriter<W> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn queue(&mut self, samples: &[f32]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            if let Err(err) = self.output.write_all(&value.to_le_bytes()) {
+                self.error = Some(err);
+                return;
+            }
+            self.data_size += 2;
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        self.output.seek(SeekFrom::Start(0))?;
+        WavWriter::write_header(&mut self.output, self.sample_rate, self.data_size)?;
+        self.output.seek(SeekFrom::End(0))?;
+        self.output.flush()
+    }
+}
+
+#[cfg(feature = "audio-device")]
+pub mod device {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    use super::AudioSink;
+
+    fn device_error<E: ToString>(err: E) -> io::Error {
+        io::Error::other(err.to_string())
+    }
+
+    pub struct DeviceSink {
+        queue: Arc<Mutex<VecDeque<f32>>>,
+        sample_rate: u32,
+        _stream: cpal::Stream,
+    }
+
+    impl DeviceSink {
+        pub fn open() -> io::Result<Self> {
+            let host = cpal::default_host();
+            let device = host.default_output_device()
+                             .ok_or_else(|| device_error("no audio output device"))?;
+            let config = device.default_output_config().map_err(device_error)?;
+
+            if config.sample_format() != cpal::SampleFormat::F32 {
+                return Err(device_error("audio device does not support f32 samples"));
+            }
+
+            let sample_rate = config.sample_rate().0;
+            let channels = config.channels() as usize;
+            let queue = Arc::new(Mutex::new(VecDeque::new()));
+            let output = queue.clone();
+
+            let stream = device.build_output_stream(
+                &config.into(),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let mut queue = output.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let sample = queue.pop_front().unwrap_or(0.0);
+                        for value in frame.iter_mut() {
+                            *value = sample;
+                        }
+                    }
+                },
+                |err| eprintln!("Audio stream error: {}", err),
+            ).map_err(device_error)?;
+            stream.play().map_err(device_error)?;
+
+            Ok(DeviceSink {
+                queue,
+                sample_rate,
+                _stream: stream,
+            })
+        }
+    }
+
+    impl AudioSink for DeviceSink {
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn queue(&mut self, samples: &[f32]) {
+            let mut queue = self.queue.lock().unwrap();
+
+            // Drop stale audio instead of letting latency build up when the
+            // emulation runs faster than the device consumes samples.
+            let limit = self.sample_rate as usize / 10;
+            if queue.len() > limit {
+                let excess = queue.len() - limit;
+                queue.drain(..excess);
+            }
+            queue.extend(samples);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    struct MemorySink {
+        samples: Rc<RefCell<Vec<f32>>>,
+    }
+
+    impl AudioSink for MemorySink {
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn queue(&mut self, samples: &[f32]) {
+            self.samples.borrow_mut().extend_from_slice(samples);
+        }
+    }
+
+    #[test]
+    fn test_square_wave() {
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        let sink = MemorySink { samples: samples.clone() };
+        let tone = Tone { frequency: 441.0, volume: 0.5 };
+        let mut beeper = Beeper::new(tone, Box::new(sink));
+
+        beeper.render_frame(true, None);
+        beeper.render_frame(false, None);
+
+        let samples = samples.borrow();
+        assert_eq!(samples.len(), 2 * 735);
+        assert!(samples[..45].iter().all(|sample| *sample == 0.5));
+        assert!(samples[55..95].iter().all(|sample| *sample == -0.5));
+        assert!(samples[105..145].iter().all(|sample| *sample == 0.5));
+        assert!(samples[735..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn test_pattern() {
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        let sink = MemorySink { samples: samples.clone() };
+        let tone = Tone { frequency: 441.0, volume: 0.5 };
+        let mut beeper = Beeper::new(tone, Box::new(sink));
+
+        let mut pattern = Pattern { bits: [0x0; AUDIO_PATTERN_SIZE], pitch: DEFAULT_PITCH };
+        pattern.bits[0] = 0xF0;
+        assert_eq!(pattern.playback_rate(), 4000.0);
+        beeper.render_frame(true, Some(&pattern));
+        beeper.render_frame(true, Some(&pattern));
+
+        // Each bit lasts 44100 / 4000 samples, the pattern loops every 128 bits.
+        let samples = samples.borrow();
+        assert!(samples[..44].iter().all(|sample| *sample == 0.5));
+        assert!(samples[45..1411].iter().all(|sample| *sample == -0.5));
+        assert!(samples[1412..1455].iter().all(|sample| *sample == 0.5));
+
+        pattern.pitch = DEFAULT_PITCH + 48;
+        assert_eq!(pattern.playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn test_wav_header() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 8000).unwrap();
+        writer.queue(&[1.0, -1.0, 0.0]);
+        writer.finish().unwrap();
+
+        let bytes = writer.output.into_inner();
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &42u32.to_le_bytes());
+        assert_eq!(&bytes[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&bytes[40..44], &6u32.to_le_bytes());
+        assert_eq!(&bytes[44..46], &i16::MAX.to_le_bytes());
+    }
+
+    #[test]
+    fn test_wav_write_error() {
+        // Room for the header and a single sample.
+        let mut buffer = [0; 46];
+        let mut writer = WavWriter::new(Cursor::new(&mut buffer[..]), 8000).unwrap();
+        writer.queue(&[1.0, -1.0]);
+        writer.queue(&[0.0]);
+
+        assert_eq!(writer.data_size, 2);
+        assert_eq!(writer.finish().unwrap_err().kind(), io::ErrorKind::WriteZero);
+    }
+}
@@ -1,38 +1,60 @@
 use crate::memory::{MainMemory, Memory, merge_bytes};
 use crate::specs::{Address, Instruction, Byte};
-use crate::display::FrameBuffer;
-use crate::input::Keyboard;
+use crate::display::{FrameBuffer, SpriteEdge};
+use crate::input::{Keyboard, KeypadState};
+use crate::watcher::{Watcher, Message};
 
 pub struct Bus {
     memory: MainMemory,
     frame_buffer: FrameBuffer,
     keyboard: Keyboard,
+    watcher: Watcher,
 }
 
 impl Bus {
-    pub fn new(memory: MainMemory) -> Self {
+    pub fn new(memory: MainMemory, watcher: Watcher) -> Self {
         Bus {
             memory,
             frame_buffer: FrameBuffer::new(),
             keyboard: Keyboard::new(),
+            watcher,
+        }
+    }
+
+    fn report_reads(&self, address: Address, size: Address) {
+        // Sprites are read on every DRW, skip the loop when nobody listens.
+        if !self.watcher.is_active() {
+            return;
+        }
+
+        for (offset, value) in self.memory.slice(address, size).iter().enumerate() {
+            self.watcher.send(Message::MemoryRead {
+                address: address.wrapping_add(offset as Address),
+                value: *value,
+            });
         }
     }
 
     pub fn read_instruction(&self, address: Address) -> Instruction {
-        let left = self.memory.read(address);
-        let right = self.memory.read(address + 1);
+        let left = self.memory.read(self.memory.wrap(address as usize));
+        let right = self.memory.read(self.memory.wrap(address as usize + 1));
         merge_bytes(left, right)
     }
 
     pub fn read_bytes(&self, address: Address, offset: Address) -> Vec<Byte> {
+        self.report_reads(address, offset);
         self.memory.read_bytes(address, offset)
     }
 
     pub fn write_bytes(&mut self, address: Address, bytes: &[Byte]) {
-        let mut addr = address;
-        for byte in bytes {
-            self.memory.write(addr, *byte);
-            addr += 1;
+        for (offset, byte) in bytes.iter().enumerate() {
+            let address = self.memory.wrap(address as usize + offset);
+            self.watcher.send(Message::MemoryWrite {
+                address,
+                old: self.memory.read(address),
+                new: *byte,
+            });
+            self.memory.write(address, *byte);
         }
     }
 
@@ -40,16 +62,54 @@ impl Bus {
         self.frame_buffer.clear_screen();
     }
 
-    pub fn press_key(&mut self, key: Option<u8>) {
-        self.keyboard.set_key_pressed(key);
+    pub fn set_keypad_state(&mut self, state: KeypadState) {
+        self.keyboard.set_state(state);
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        self.keyboard.is_pressed(key)
     }
 
     pub fn get_key_pressed(&self) -> Option<u8> {
         self.keyboard.get_key_pressed()
     }
 
-    pub fn display_sprite(&mut self, pos: (usize, usize), sprite: &[u8]) -> bool {
-        self.frame_buffer.write_bytes(pos, sprite)
+    pub fn display_sprite(&mut self, pos: (usize, usize), address: Address, size: Address) -> bool {
+        self.report_reads(address, size);
+        self.frame_buffer.write_bytes(pos, self.memory.slice(address, size))
+    }
+
+    pub fn display_wide_sprite(&mut self, pos: (usize, usize), address: Address, size: Address) -> bool {
+        self.report_reads(address, size);
+        self.frame_buffer.write_wide_bytes(pos, self.memory.slice(address, size))
+    }
+
+    pub fn set_sprite_edge(&mut self, edge: SpriteEdge) {
+        self.frame_buffer.set_sprite_edge(edge);
+    }
+
+    pub fn set_hires(&mut self, hires: bool) {
+        self.frame_buffer.set_hires(hires);
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.frame_buffer.scroll_down(rows);
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.frame_buffer.scroll_up(rows);
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.frame_buffer.select_planes(mask);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.frame_buffer.scroll_right(columns);
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.frame_buffer.scroll_left(columns);
     }
 
     pub fn get_frame_buffer(&self) -> &FrameBuffer {
@@ -1,18 +1,53 @@
-use std::path::{Path};
+use std::path::{Path, PathBuf};
 
 use crate::asm;
+use crate::assembler;
 
 use crate::memory;
 use crate::debugger;
+use crate::debugger_tui::DebuggerTui;
+use crate::gdb;
+use crate::dap;
+use crate::lines::LineTable;
 use crate::window;
 use crate::watcher;
+use crate::headless;
+use crate::frontend;
+use crate::tui;
+use crate::audio::{self, Beeper, Tone, WavWriter};
+use crate::machine::Machine;
+use crate::movie::Movie;
+use crate::specs::Platform;
+use crate::display::SpriteEdge;
+use crate::palette::Palette;
+use crate::screenshot::Screenshot;
+use crate::recorder::GifRecorder;
+use crate::filter::FilterMode;
+use crate::symbols::SymbolTable;
 
 pub mod error {
+    use std::fmt;
     use std::io;
 
+    use crate::movie::error::MovieError;
+    use crate::symbols::error::SymbolError;
+    use crate::lines::error::LineError;
+    use crate::memory::error::MemoryError;
+    use crate::assembler::error::AssemblerError;
+    use crate::cpu::error::CPUError;
+    use crate::palette::error::PaletteError;
+
     #[derive(Debug)]
     pub enum CLIError {
         IOError(io::Error),
+        MovieError(MovieError),
+        SymbolError(SymbolError),
+        LineError(LineError),
+        MemoryError(MemoryError),
+        AssemblerError(AssemblerError),
+        CPUError(CPUError),
+        PaletteError(PaletteError),
+        MissingArgument(&'static str),
     }
 
     impl From<io::Error> for CLIError {
@@ -20,22 +55,243 @@ pub mod error {
             CLIError::IOError(err)
         }
     }
+
+    impl From<MovieError> for CLIError {
+        fn from(err: MovieError) -> Self {
+            CLIError::MovieError(err)
+        }
+    }
+
+    impl From<SymbolError> for CLIError {
+        fn from(err: SymbolError) -> Self {
+            CLIError::SymbolError(err)
+        }
+    }
+
+    impl From<LineError> for CLIError {
+        fn from(err: LineError) -> Self {
+            CLIError::LineError(err)
+        }
+    }
+
+    impl From<MemoryError> for CLIError {
+        fn from(err: MemoryError) -> Self {
+            CLIError::MemoryError(err)
+        }
+    }
+
+    impl From<AssemblerError> for CLIError {
+        fn from(err: AssemblerError) -> Self {
+            CLIError::AssemblerError(err)
+        }
+    }
+
+    impl From<CPUError> for CLIError {
+        fn from(err: CPUError) -> Self {
+            CLIError::CPUError(err)
+        }
+    }
+
+    impl From<PaletteError> for CLIError {
+        fn from(err: PaletteError) -> Self {
+            CLIError::PaletteError(err)
+        }
+    }
+
+    impl fmt::Display for CLIError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                CLIError::IOError(err) => write!(f, "I/O error: {}", err),
+                CLIError::MovieError(err) => write!(f, "{}", err),
+                CLIError::SymbolError(err) => write!(f, "{}", err),
+                CLIError::LineError(err) => write!(f, "{}", err),
+                CLIError::MemoryError(err) => write!(f, "{}", err),
+                CLIError::AssemblerError(err) => write!(f, "{}", err),
+                CLIError::CPUError(err) => write!(f, "{}", err),
+                CLIError::PaletteError(err) => write!(f, "{}", err),
+                CLIError::MissingArgument(arg) => write!(f, "missing argument: {}", arg),
+            }
+        }
+    }
+}
+
+pub struct VmOptions {
+    pub debug: bool,
+    pub verbose: bool,
+    pub headless: bool,
+    pub tui: bool,
+    pub frames: Option<u64>,
+    pub platform: Platform,
+    pub sprite_edge: SpriteEdge,
+    pub symbols: Option<PathBuf>,
+    pub gdb: Option<String>,
+    pub dap: Option<String>,
+    pub lines: Option<PathBuf>,
+    pub instructions_per_frame: Option<usize>,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub wav: Option<PathBuf>,
+    pub screenshot: Option<PathBuf>,
+    pub screenshot_scale: usize,
+    pub gif: Option<PathBuf>,
+    pub gif_scale: usize,
+    pub gif_interval: u64,
+    pub filter: FilterMode,
+    pub palette: Palette,
+    pub palette_file: Option<PathBuf>,
+    pub grid: bool,
+    pub border: usize,
+    pub scale: usize,
+    pub crt: bool,
+    pub tone: Tone,
 }
 
-pub fn emulate(path: &Path, debug: bool, verbose: bool) -> Result<(), error::CLIError> {
+#[cfg(feature = "audio-device")]
+fn device_beeper(tone: Tone) -> Option<Beeper> {
+    match audio::device::DeviceSink::open() {
+        Ok(sink) => Some(Beeper::new(tone, Box::new(sink))),
+        Err(err) => {
+            eprintln!("Audio disabled: {}", err);
+            None
+        },
+    }
+}
+
+#[cfg(not(feature = "audio-device"))]
+fn device_beeper(_tone: Tone) -> Option<Beeper> {
+    None
+}
+
+pub fn emulate(path: &Path, mut options: VmOptions) -> Result<(), error::CLIError> {
     let rom = memory::ROM::from_file(path)?;
+    if let Some(ref palette_path) = options.palette_file {
+        options.palette = Palette::load(palette_path)?;
+    }
     let mut watcher = watcher::Watcher::new();
 
-    watcher.verbose = verbose;
+    // Verbose messages go to stdout, where they would be written over the
+    // full-screen debugger or mixed into the DAP stream.
+    let full_screen = options.debug && options.tui;
+    if options.verbose && (full_screen || options.dap.is_some()) {
+        eprintln!("--verbose is ignored with --tui --debug and --dap.");
+    } else {
+        watcher.verbose = options.verbose;
+    }
+
+    let mut machine = match options.play {
+        Some(ref movie_path) => {
+            Machine::from_movie(rom, watcher, Movie::load(movie_path)?)?
+        },
+        None => {
+            let seed = rand::random();
+            let mut machine = Machine::new(rom, watcher, seed, options.platform)?;
+            machine.set_sprite_edge(options.sprite_edge);
+            if let Some(instructions) = options.instructions_per_frame {
+                machine.set_instructions_per_frame(instructions);
+            }
+            if options.record.is_some() {
+                machine.start_recording(seed);
+            }
+            machine
+        },
+    };
+
+    if let Some(ref wav_path) = options.wav {
+        let writer = WavWriter::create(wav_path, audio::SAMPLE_RATE)?;
+        machine.set_audio(Beeper::new(options.tone, Box::new(writer)));
+    } else if !options.debug && !options.headless && options.gdb.is_none() && options.dap.is_none() {
+        if let Some(beeper) = device_beeper(options.tone) {
+            machine.set_audio(beeper);
+        }
+    }
+
+    if let Some(ref gif_path) = options.gif {
+        let recorder = GifRecorder::create(gif_path, &options.palette,
+                                           options.gif_scale, options.gif_interval)?;
+        machine.set_gif_recorder(recorder);
+    }
+
+    let screenshot = Screenshot::new(options.palette, options.screenshot_scale);
 
-    if debug {
-        let mut debugger = debugger::Debugger::new(rom, watcher);
-        debugger.run();
+    if options.debug || options.dap.is_some() {
+        let mut debugger = debugger::Debugger::new(machine, screenshot);
+        if let Some(ref symbols_path) = options.symbols {
+            debugger.set_symbols(SymbolTable::load(symbols_path)?);
+        }
+        // Sidecars next to the ROM are picked up unless a table was given,
+        // a broken one only gets a warning.
+        let lines = match options.lines {
+            Some(ref lines_path) => Some((LineTable::load(lines_path)?, lines_path.clone())),
+            None => {
+                let sidecar = path.with_extension("lines");
+                match sidecar.is_file().then(|| LineTable::load(&sidecar)) {
+                    Some(Ok(table)) => Some((table, sidecar)),
+                    Some(Err(err)) => {
+                        eprintln!("Ignoring {}: {}", sidecar.display(), err);
+                        None
+                    },
+                    None => None,
+                }
+            },
+        };
+        if let Some((table, lines_path)) = lines {
+            debugger.set_line_table(table);
+            debugger.load_sources(lines_path.parent().unwrap_or_else(|| Path::new(".")));
+        }
+        if let Some(ref address) = options.dap {
+            debugger = dap::serve(address, debugger)?;
+        } else if options.tui {
+            DebuggerTui::new()?.run(&mut debugger)?;
+        } else {
+            debugger.run();
+        }
+        machine = debugger.into_machine();
+    } else if let Some(ref address) = options.gdb {
+        machine = gdb::listen(address, machine)?;
+    } else if options.headless {
+        let frames = options.frames
+                            .or_else(|| machine.get_movie_length())
+                            .ok_or(error::CLIError::MissingArgument("--frames"))?;
+        let mut headless = headless::Headless::new(machine, frames);
+        headless.run();
+        machine = headless.into_machine();
+        match options.screenshot {
+            Some(ref path) => screenshot.save(machine.get_bus().get_frame_buffer(), path)?,
+            None => println!("{}", machine.get_bus().get_frame_buffer()),
+        }
+    } else if options.tui {
+        let mut terminal = tui::Terminal::new()?;
+        frontend::run(&mut terminal, &mut machine, &screenshot);
     } else {
-        let mut window = window::Window::new(rom, watcher);
-        window.run();
+        let mut window = window::Window::new(window::WindowOptions {
+            palette: options.palette,
+            filter: options.filter,
+            grid: options.grid,
+            border: options.border,
+            scale: options.scale,
+            crt: options.crt,
+        });
+        frontend::run(&mut window, &mut machine, &screenshot);
     }
 
+    machine.finish_audio()?;
+    machine.finish_gif()?;
+
+    if let (Some(record_path), Some(movie)) = (options.record, machine.get_recording()) {
+        movie.save(&record_path)?;
+    }
+
+    match machine.get_cpu().get_error() {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+pub fn assemble(path: &Path, rom: &Path, lines: Option<&Path>) -> Result<(), error::CLIError> {
+    let program = assembler::assemble_file(path, lines)?;
+    program.save(rom, lines)?;
+
+    println!("Assembled {} bytes to {}.", program.bytes.len(), rom.display());
     Ok(())
 }
 
@@ -9,17 +9,47 @@ use crate::specs::{
     REGISTERS_COUNT,
     STACK_SIZE,
     PROGRAM_BEGIN,
+    BIG_FONT_BEGIN,
+    Platform,
+    FLAGS_COUNT,
+    AUDIO_PATTERN_SIZE,
+    DEFAULT_PITCH,
 };
 use crate::asm::{
     InstructionData,
     decode_instruction,
 };
 use crate::bus::Bus;
+use crate::audio::Pattern;
 use crate::watcher::{Watcher, Message};
 use std::fmt;
-use rand::Rng;
-use rand::prelude::ThreadRng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
+pub mod error {
+    use std::fmt;
+
+    use crate::specs::Address;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CPUError {
+        StackOverflow(Address),
+    }
+
+    impl fmt::Display for CPUError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                CPUError::StackOverflow(address) => {
+                    write!(f, "stack overflow on the call at {:#05X}", address)
+                },
+            }
+        }
+    }
+}
+
+use error::CPUError;
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     i: Register<Address>,
     pc: Register<Address>,
@@ -30,13 +60,21 @@ pub struct CPU {
 
     delay_timer: Byte,
     sound_timer: Byte,
+    audio_pattern: Option<[Byte; AUDIO_PATTERN_SIZE]>,
+    pitch: Byte,
 
-    random_device: ThreadRng,
+    flags: [Byte; FLAGS_COUNT],
+    exited: bool,
+    error: Option<CPUError>,
+    platform: Platform,
+
+    seed: u64,
+    random_device: StdRng,
     watcher: Watcher,
 }
 
 impl CPU {
-    pub fn new(watcher: Watcher) -> Self {
+    pub fn new(watcher: Watcher, seed: u64, platform: Platform) -> Self {
         CPU {
             i: 0x0,
             pc: PROGRAM_BEGIN as Address,
@@ -45,7 +83,14 @@ impl CPU {
             sp: 0x0,
             delay_timer: 0,
             sound_timer: 0,
-            random_device: rand::thread_rng(),
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            flags: [0x0; FLAGS_COUNT],
+            exited: false,
+            error: None,
+            platform,
+            seed,
+            random_device: StdRng::seed_from_u64(seed),
             watcher
         }
     }
@@ -58,13 +103,18 @@ impl CPU {
         self.sp = 0x0;
         self.delay_timer = 0;
         self.sound_timer = 0;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.exited = false;
+        self.error = None;
+        self.random_device = StdRng::seed_from_u64(self.seed);
     }
 
-    fn get_reg(&self, index: Nibble) -> Byte {
+    pub fn get_reg(&self, index: Nibble) -> Byte {
         self.registers[index as usize]
     }
 
-    fn set_reg(&mut self, index: Nibble, value: Byte) {
+    pub fn set_reg(&mut self, index: Nibble, value: Byte) {
         self.watcher.send(Message::RegisterChange {
             id: index,
             old: self.registers[index as usize],
@@ -73,9 +123,23 @@ impl CPU {
         self.registers[index as usize] = value;
     }
 
+    fn register_range(x: Nibble, y: Nibble) -> Box<dyn Iterator<Item = Nibble>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
+    }
+
+    fn skip(&mut self, bus: &Bus) {
+        // The XO-CHIP long load is the only instruction spanning four bytes.
+        let long = self.platform == Platform::XoChip && bus.read_instruction(self.pc) == 0xF000;
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
     fn fetch(&mut self, bus: &mut Bus) -> Instruction {
         let instr = bus.read_instruction(self.pc);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
         instr
     }
 
@@ -87,6 +151,9 @@ impl CPU {
         use InstructionData::*;
 
         match data {
+            // Extensions of later platforms are ignored like machine code
+            // routines.
+            data if data.platform() > self.platform => {},
             Cls => bus.clear_screen(),
             Ret => {
                 if self.sp == 0 {
@@ -96,27 +163,21 @@ impl CPU {
                 self.sp -= 1;
             },
             Jp(n) => self.pc = n,
+            Call(_) if self.sp as usize == STACK_SIZE => {
+                // Halts on the call, like EXIT.
+                self.pc = self.pc.wrapping_sub(2);
+                self.error = Some(CPUError::StackOverflow(self.pc));
+                self.exited = true;
+            },
             Call(n) => {
                 self.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
                 self.pc = n;
             },
-            Se(x, n) => {
-                if self.get_reg(x) == n {
-                    self.pc += 2;
-                }
-            },
-            Sne(x, n) => {
-                if self.get_reg(x) != n {
-                    self.pc += 2;
-                }
-            },
-            SeReg(x, y) => {
-                if self.get_reg(x) == self.get_reg(y) {
-                    self.pc += 2;
-                }
-            },
-            Ld(x, n) => self.registers[x as usize] = n,
+            Se(x, n) if self.get_reg(x) == n => self.skip(bus),
+            Sne(x, n) if self.get_reg(x) != n => self.skip(bus),
+            SeReg(x, y) if self.get_reg(x) == self.get_reg(y) => self.skip(bus),
+            Ld(x, n) => self.set_reg(x, n),
             Add(x, n) => {
                 let sum: u16 = self.get_reg(x) as u16 + n as u16;
                 if sum > 0xFF {
@@ -173,11 +234,7 @@ impl CPU {
                 }
                 self.set_reg(x, x_val * 2);
             },
-            SneReg(x, y) => {
-                if self.get_reg(x) != self.get_reg(y) {
-                    self.pc += 2;
-                }
-            },
+            SneReg(x, y) if self.get_reg(x) != self.get_reg(y) => self.skip(bus),
             LdI(n) => self.i = n,
             JpV0(n) => {
                 self.pc = self.get_reg(0) as u16 + n;
@@ -186,29 +243,24 @@ impl CPU {
                 let random: u16 = self.random_device.gen_range(0, 256);
                 self.set_reg(x, (random as u8) & n);
             },
-            Drw(x, y, n) => {
+            Drw(x, y, 0) => {
                 let x_val = self.get_reg(x) as usize;
                 let y_val = self.get_reg(y) as usize;
-                let bytes = bus.read_bytes(self.i, n as Address);
-                if bus.display_sprite((x_val, y_val), &bytes[..]) {
+                let planes = bus.get_frame_buffer().selected_planes_count() as Address;
+                if bus.display_wide_sprite((x_val, y_val), self.i, 32 * planes) {
                     self.set_reg(0xF, 1);
                 }
             },
-            Skp(x) => {
-                let val = self.get_reg(x);
-                match bus.get_key_pressed() {
-                    Some(key) if key == val => self.pc += 2,
-                    _ => {}
-                }
-            },
-            Sknp(x) => {
-                let val = self.get_reg(x);
-                match bus.get_key_pressed() {
-                    Some(key) if key != val => self.pc += 2,
-                    None => self.pc += 2,
-                    _ => {}
+            Drw(x, y, n) => {
+                let x_val = self.get_reg(x) as usize;
+                let y_val = self.get_reg(y) as usize;
+                let planes = bus.get_frame_buffer().selected_planes_count() as Address;
+                if bus.display_sprite((x_val, y_val), self.i, n as Address * planes) {
+                    self.set_reg(0xF, 1);
                 }
             },
+            Skp(x) if bus.is_key_pressed(self.get_reg(x)) => self.skip(bus),
+            Sknp(x) if !bus.is_key_pressed(self.get_reg(x)) => self.skip(bus),
             LdF(x) => {
                 let font_index: Byte = self.get_reg(x);
                 self.i = (font_index * 5) as u16;
@@ -219,7 +271,7 @@ impl CPU {
             LdK(x) => {
                 match bus.get_key_pressed() {
                     Some(key) => self.set_reg(x, key),
-                    None => self.pc -= 2,
+                    None => self.pc = self.pc.wrapping_sub(2),
                 }
             },
             LdDtReg(x) => {
@@ -229,7 +281,7 @@ impl CPU {
                 self.sound_timer = self.get_reg(x);
             },
             AddI(x) => {
-                self.i = self.i + self.get_reg(x) as u16;
+                self.i = self.i.wrapping_add(self.get_reg(x) as u16);
             },
             LdB(x) => {
                 let val = self.get_reg(x);
@@ -247,6 +299,53 @@ impl CPU {
                     self.set_reg(idx as u8, *byte);
                 }
             },
+            Scd(n) => bus.scroll_down(n as usize),
+            Scr => bus.scroll_right(4),
+            Scl => bus.scroll_left(4),
+            Exit => {
+                self.exited = true;
+                self.pc = self.pc.wrapping_sub(2);
+            },
+            Low => bus.set_hires(false),
+            High => bus.set_hires(true),
+            LdHf(x) => {
+                let font_index = self.get_reg(x) as Address;
+                self.i = BIG_FONT_BEGIN as Address + font_index * 10;
+            },
+            LdR(x) => {
+                let count = (x as usize + 1).min(FLAGS_COUNT);
+                self.flags[..count].copy_from_slice(&self.registers[..count]);
+            },
+            LdVxR(x) => {
+                let count = (x as usize + 1).min(FLAGS_COUNT);
+                for idx in 0..count {
+                    self.set_reg(idx as u8, self.flags[idx]);
+                }
+            },
+            Scu(n) => bus.scroll_up(n as usize),
+            SaveRange(x, y) => {
+                let bytes: Vec<Byte> = Self::register_range(x, y).map(|idx| self.get_reg(idx)).collect();
+                bus.write_bytes(self.i, &bytes);
+            },
+            LoadRange(x, y) => {
+                let bytes = bus.read_bytes(self.i, (x as i16 - y as i16).unsigned_abs() + 1);
+                for (idx, byte) in Self::register_range(x, y).zip(bytes) {
+                    self.set_reg(idx, byte);
+                }
+            },
+            LdILong => {
+                self.i = bus.read_instruction(self.pc);
+                self.pc = self.pc.wrapping_add(2);
+            },
+            Plane(n) => bus.select_planes(n),
+            Audio => {
+                let mut pattern = [0x0; AUDIO_PATTERN_SIZE];
+                pattern.copy_from_slice(&bus.read_bytes(self.i, AUDIO_PATTERN_SIZE as Address));
+                self.audio_pattern = Some(pattern);
+            },
+            LdPitch(x) => {
+                self.pitch = self.get_reg(x);
+            },
             Unknown => panic!("Illegal instruction, aborting..."),
             _ => {}
         }
@@ -256,12 +355,70 @@ impl CPU {
         self.pc
     }
 
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // Why the program stopped, when it did not run EXIT.
+    pub fn get_error(&self) -> Option<CPUError> {
+        self.error
+    }
+
     pub fn tick(&mut self, bus: &mut Bus) -> Address {
+        if self.exited {
+            return self.pc;
+        }
+
         let instr = self.fetch(bus);
         let data = self.decode(instr);
         self.execute(data, bus)
     }
 
+    pub fn get_pc(&self) -> Address {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, address: Address) {
+        self.pc = address;
+    }
+
+    pub fn get_i(&self) -> Address {
+        self.i
+    }
+
+    pub fn set_i(&mut self, address: Address) {
+        self.i = address;
+    }
+
+    pub fn get_sp(&self) -> Byte {
+        self.sp
+    }
+
+    // Return addresses of the calls in progress, outermost first.
+    pub fn get_stack(&self) -> &[Address] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn get_delay_timer(&self) -> Byte {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: Byte) {
+        self.delay_timer = value;
+    }
+
+    pub fn get_sound_timer(&self) -> Byte {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: Byte) {
+        self.sound_timer = value;
+    }
+
+    pub fn get_audio_pattern(&self) -> Option<Pattern> {
+        self.audio_pattern.map(|bits| Pattern { bits, pitch: self.pitch })
+    }
+
     pub fn timer_decrement(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
@@ -277,18 +434,18 @@ impl fmt::Display for CPU {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use termion::{color, style};
 
-        write!(f, "I = {}{:#05x}{}, PC = {}{:#05x}{}\n",
+        writeln!(f, "I = {}{:#05x}{}, PC = {}{:#05x}{}",
                style::Bold, self.i, style::Reset,
                style::Bold, self.pc, style::Reset)?;
 
-        write!(f, "DT = {}{:#04x}{}, ST = {}{:#04x}{}\n",
+        writeln!(f, "DT = {}{:#04x}{}, ST = {}{:#04x}{}",
                style::Bold, self.delay_timer, style::Reset,
                style::Bold, self.sound_timer, style::Reset)?;
-        write!(f, "\n")?;
+        writeln!(f)?;
 
         for (idx, regs) in self.registers.chunks(2).enumerate() {
             let (left, right) = (regs[0], regs[1]);
-            write!(f, "V{:1X} = {}{:#04x}{}, V{:1X} = {}{:#04x}{}\n",
+            writeln!(f, "V{:1X} = {}{:#04x}{}, V{:1X} = {}{:#04x}{}",
                    idx * 2, style::Bold, left, style::Reset,
                    idx * 2 + 1, style::Bold, right, style::Reset)?;
         }
@@ -311,3 +468,90 @@ impl fmt::Display for CPU {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MainMemory;
+
+    fn cpu() -> (CPU, Bus) {
+        let mut cpu = CPU::new(Watcher::new(), 0, Platform::Chip8);
+        cpu.registers[..4].copy_from_slice(&[0x1, 0x2, 0x3, 0x4]);
+        (cpu, Bus::new(MainMemory::new(), Watcher::new()))
+    }
+
+    #[test]
+    fn test_accesses_past_the_end_of_memory() {
+        let (mut cpu, mut bus) = cpu();
+        cpu.i = 0xFFE;
+
+        // Sprites are cut short while stores and loads wrap around.
+        cpu.execute(InstructionData::Drw(0, 0, 5), &mut bus);
+        cpu.execute(InstructionData::LdIMem(4), &mut bus);
+        assert_eq!(bus.read_bytes(0xFFE, 4), vec![0x1, 0x2, 0x3, 0x4]);
+        assert_eq!(bus.read_instruction(0xFFF), 0x0203);
+
+        cpu.execute(InstructionData::LdVx(1), &mut bus);
+        assert_eq!(&cpu.registers[..2], &[0x1, 0x2]);
+    }
+
+    #[test]
+    fn test_platform_extensions() {
+        let (mut cpu, mut bus) = cpu();
+
+        cpu.execute(InstructionData::High, &mut bus);
+        cpu.execute(InstructionData::Exit, &mut bus);
+        assert_eq!(bus.get_frame_buffer().width(), 64);
+        assert!(!cpu.has_exited());
+
+        cpu.platform = Platform::SuperChip;
+        cpu.execute(InstructionData::High, &mut bus);
+        assert_eq!(bus.get_frame_buffer().width(), 128);
+    }
+
+    #[test]
+    fn test_end_of_extended_memory() {
+        let mut cpu = CPU::new(Watcher::new(), 0, Platform::XoChip);
+        let mut bus = Bus::new(MainMemory::with_size(Platform::XoChip.memory_size()), Watcher::new());
+        cpu.registers[0] = 0x4;
+        cpu.pc = 0xFFFE;
+        cpu.i = 0xFFFE;
+        // LD V1, 0x00
+        bus.write_bytes(0xFFFE, &[0x61, 0x00]);
+
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.pc, 0x0);
+
+        cpu.execute(InstructionData::AddI(0), &mut bus);
+        assert_eq!(cpu.i, 0x2);
+    }
+
+    #[test]
+    fn test_skip_long_load() {
+        let (mut cpu, mut bus) = cpu();
+        bus.write_bytes(0x202, &[0xF0, 0x00]);
+
+        cpu.pc = 0x202;
+        cpu.execute(InstructionData::Se(0, 0x1), &mut bus);
+        assert_eq!(cpu.pc, 0x204);
+
+        cpu.platform = Platform::XoChip;
+        cpu.pc = 0x202;
+        cpu.execute(InstructionData::Se(0, 0x1), &mut bus);
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let (mut cpu, mut bus) = cpu();
+        // CALL 0x200
+        bus.write_bytes(0x200, &[0x22, 0x00]);
+
+        for _ in 0..STACK_SIZE + 1 {
+            cpu.tick(&mut bus);
+        }
+        assert!(cpu.has_exited());
+        assert_eq!(cpu.get_error(), Some(CPUError::StackOverflow(0x200)));
+        assert_eq!(cpu.get_stack().len(), STACK_SIZE);
+    }
+}
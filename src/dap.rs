@@ -0,0 +1,680 @@
+extern crate serde_json;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::debugger::{Debugger, Stop};
+use crate::expr::{Expr, Location};
+use crate::specs::{Address, REGISTERS_COUNT};
+
+// Debug Adapter Protocol server, so editors can drive the debugger.
+
+// Instructions run between two checks for requests such as `pause`.
+const INSTRUCTIONS_PER_POLL: usize = 1000;
+// Larger bodies are refused rather than allocated.
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+const STACK_REFERENCE: u64 = 2;
+
+static BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut output = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate()
+                         .fold(0u32, |value, (idx, byte)| value | (*byte as u32) << (16 - idx * 8));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                output.push(BASE64[(value >> (18 - idx * 6)) as usize & 0x3F] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+fn parse_reference(text: &str) -> Option<Address> {
+    Address::from_str_radix(text.trim_start_matches("0x"), 16).ok()
+}
+
+// Messages are JSON bodies preceded by a `Content-Length` header. Returns
+// `None` at the end of the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::other("missing Content-Length header"))?;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::other(format!("message of {} bytes is too large", length)));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::other)
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BreakpointRequest {
+    address: Address,
+    condition: Option<String>,
+}
+
+pub struct DapServer<W: Write> {
+    debugger: Debugger,
+    writer: W,
+    seq: u64,
+    stop_on_entry: bool,
+    // Breakpoints are set per source file, and once more for addresses.
+    sources: BTreeMap<String, Vec<BreakpointRequest>>,
+    instructions: Vec<BreakpointRequest>,
+    // The debugger's numbers for them, breakpoints set from the console
+    // are left alone.
+    numbers: Vec<usize>,
+    done: bool,
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(mut debugger: Debugger, writer: W) -> Self {
+        debugger.set_context_output(false);
+
+        DapServer {
+            debugger,
+            writer,
+            seq: 0,
+            stop_on_entry: false,
+            sources: BTreeMap::new(),
+            instructions: Vec::new(),
+            numbers: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.writer, &message)
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.event("stopped", json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        }))
+    }
+
+    // Forwards what the debugger printed to the editor's console.
+    fn flush_output(&mut self) -> io::Result<()> {
+        for line in self.debugger.take_output() {
+            self.event("output", json!({ "category": "console", "output": format!("{}\n", line) }))?;
+        }
+        Ok(())
+    }
+
+    // The debugger numbers breakpoints itself, the editor's are all
+    // recreated when it changes any of them.
+    fn sync_breakpoints(&mut self) {
+        for number in std::mem::take(&mut self.numbers) {
+            self.debugger.process_input(&format!("delete {}", number));
+        }
+
+        let requests: Vec<BreakpointRequest> = self.sources.values().flatten()
+                                                   .chain(self.instructions.iter())
+                                                   .cloned()
+                                                   .collect();
+        for request in requests {
+            match request.condition {
+                Some(ref condition) => {
+                    self.debugger.process_input(&format!("break {:#X} if {}", request.address, condition))
+                },
+                None => self.debugger.process_input(&format!("break {:#X}", request.address)),
+            }
+            self.numbers.push(self.debugger.get_breakpoint_count());
+        }
+
+        // Confirmations would only clutter the console.
+        self.debugger.take_output();
+    }
+
+    // Conditions are checked here, the editor shows why one is rejected.
+    fn condition(breakpoint: &Value) -> Result<Option<String>, String> {
+        match breakpoint["condition"].as_str().filter(|condition| !condition.is_empty()) {
+            Some(condition) => {
+                Expr::parse(condition).map_err(|err| format!("Invalid condition: {}", err))?;
+                Ok(Some(condition.to_owned()))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"].as_str().ok_or("Missing source path")?.to_owned();
+        let mut requests = Vec::new();
+        let mut breakpoints = Vec::new();
+
+        for breakpoint in args["breakpoints"].as_array().cloned().unwrap_or_default() {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+            match self.debugger.get_line_table().address_of(&path, line) {
+                Some((address, line)) => match DapServer::<W>::condition(&breakpoint) {
+                    Ok(condition) => {
+                        requests.push(BreakpointRequest { address, condition });
+                        breakpoints.push(json!({
+                            "verified": true,
+                            "line": line,
+                            "instructionReference": format!("{:#05X}", address),
+                        }));
+                    },
+                    Err(message) => breakpoints.push(json!({
+                        "verified": false,
+                        "line": line,
+                        "message": message,
+                    })),
+                },
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at this line",
+                })),
+            }
+        }
+
+        self.sources.insert(path, requests);
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let mut breakpoints = Vec::new();
+        self.instructions.clear();
+
+        for breakpoint in args["breakpoints"].as_array().cloned().unwrap_or_default() {
+            let address = breakpoint["instructionReference"].as_str()
+                .and_then(parse_reference)
+                .map(|address| address.wrapping_add(breakpoint["offset"].as_i64().unwrap_or(0) as Address));
+            match address {
+                Some(address) => match DapServer::<W>::condition(&breakpoint) {
+                    Ok(condition) => {
+                        self.instructions.push(BreakpointRequest { address, condition });
+                        breakpoints.push(json!({
+                            "verified": true,
+                            "instructionReference": format!("{:#05X}", address),
+                        }));
+                    },
+                    Err(message) => breakpoints.push(json!({ "verified": false, "message": message })),
+                },
+                None => breakpoints.push(json!({ "verified": false, "message": "Invalid address" })),
+            }
+        }
+
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> Value {
+        let frames: Vec<Value> = self.debugger.frames().into_iter().enumerate().map(|(level, address)| {
+            let name = self.debugger.get_symbols().describe(address)
+                           .unwrap_or_else(|| format!("{:#05X}", address));
+            let mut frame = json!({
+                "id": level,
+                "name": name,
+                "line": 0,
+                "column": 0,
+                "instructionPointerReference": format!("{:#05X}", address),
+            });
+            if let Some(source) = self.debugger.get_line_table().lookup(address) {
+                frame["source"] = json!({ "path": source.file });
+                frame["line"] = json!(source.line);
+            }
+            frame
+        }).collect();
+
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&self, reference: u64) -> Value {
+        let machine = self.debugger.get_machine();
+        let variable = |name: String, value: String| json!({
+            "name": name,
+            "value": value,
+            "variablesReference": 0,
+        });
+
+        let variables: Vec<Value> = match reference {
+            REGISTERS_REFERENCE => {
+                let mut variables: Vec<Value> = (0..REGISTERS_COUNT as u8).map(|index| {
+                    let value = Location::Register(index).read(machine);
+                    variable(format!("V{:X}", index), format!("{:#04X}", value))
+                }).collect();
+
+                for (name, location) in &[("I", Location::I), ("PC", Location::PC)] {
+                    let mut register = variable(name.to_string(), format!("{:#05X}", location.read(machine)));
+                    register["memoryReference"] = json!(format!("{:#05X}", location.read(machine)));
+                    variables.push(register);
+                }
+                for (name, location) in &[("SP", Location::SP), ("DT", Location::DT), ("ST", Location::ST)] {
+                    variables.push(variable(name.to_string(), format!("{:#04X}", location.read(machine))));
+                }
+                variables
+            },
+            STACK_REFERENCE => {
+                machine.get_cpu().get_stack().iter().enumerate().rev().map(|(level, ret)| {
+                    let mut value = format!("{:#05X}", ret);
+                    if let Some(name) = self.debugger.get_symbols().describe(*ret) {
+                        value += &format!(" ({})", name);
+                    }
+                    variable(format!("{}", level), value)
+                }).collect()
+            },
+            _ => Vec::new(),
+        };
+
+        json!({ "variables": variables })
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        let name = args["name"].as_str().ok_or("Missing variable name")?;
+        let value = args["value"].as_str().ok_or("Missing value")?;
+        let location = Location::parse(name).ok_or_else(|| format!("{} cannot be set", name))?;
+
+        self.debugger.process_input(&format!("set {} = {}", name, value));
+        let output = self.debugger.take_output();
+        if !output.is_empty() {
+            return Err(output.join("\n"));
+        }
+
+        Ok(json!({ "value": format!("{:#04X}", location.read(self.debugger.get_machine())) }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let address = args["memoryReference"].as_str().and_then(parse_reference)
+                                             .ok_or("Invalid memory reference")? as i64
+                      + args["offset"].as_i64().unwrap_or(0);
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let ram = self.debugger.get_machine().get_bus().get_ram();
+
+        if address < 0 || address as usize >= ram.size() {
+            return Ok(json!({ "address": format!("{:#05X}", address), "unreadableBytes": count }));
+        }
+
+        let address = address as usize;
+        let readable = count.min(ram.size() - address);
+        let bytes = ram.slice(address as Address, readable as Address);
+        Ok(json!({
+            "address": format!("{:#05X}", address),
+            "data": base64(bytes),
+            "unreadableBytes": count - readable,
+        }))
+    }
+
+    // Runs a debugger command that either stops right away or resumes
+    // execution, which then stops in `poll`.
+    fn execute(&mut self, command: &str) -> io::Result<()> {
+        self.debugger.process_input(command);
+        if !self.debugger.is_running() {
+            self.stopped("step")?;
+        }
+        Ok(())
+    }
+
+    pub fn handle(&mut self, request: &Value) -> io::Result<()> {
+        let args = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or("");
+
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsSetVariable": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" | "attach" => {
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                Ok(Value::Null)
+            },
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+            ]})),
+            "variables" => Ok(self.variables(args["variablesReference"].as_u64().unwrap_or(0))),
+            "setVariable" => self.set_variable(args),
+            "readMemory" => self.read_memory(args),
+            // The console accepts the same commands as the line debugger.
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or("");
+                self.debugger.process_input(expression);
+                Ok(json!({ "result": self.debugger.take_output().join("\n"), "variablesReference": 0 }))
+            },
+            "configurationDone" | "continue" | "next" | "stepIn" | "stepOut" | "pause" => {
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Value::Null)
+            },
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+        self.respond(request, result)?;
+
+        // Events about the request follow its response.
+        match command {
+            "initialize" => self.event("initialized", Value::Null)?,
+            "configurationDone" if self.stop_on_entry => self.stopped("entry")?,
+            "configurationDone" | "continue" => self.debugger.process_input("continue"),
+            "next" => self.execute("next")?,
+            "stepIn" => self.execute("step")?,
+            "stepOut" => self.execute("finish")?,
+            "pause" if self.debugger.is_running() => {
+                self.debugger.interrupt();
+                self.stopped("pause")?;
+            },
+            "terminate" => self.event("terminated", Value::Null)?,
+            _ => {},
+        }
+
+        self.flush_output()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.debugger.is_running()
+    }
+
+    // Runs the program for a while and reports where it stopped.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let stepping = self.debugger.has_target();
+
+        for _ in 0..INSTRUCTIONS_PER_POLL {
+            match self.debugger.advance() {
+                Some(Stop::Exited) => {
+                    self.event("exited", json!({ "exitCode": 0 }))?;
+                    self.event("terminated", Value::Null)?;
+                    self.done = true;
+                    break;
+                },
+                Some(Stop::Breakpoint(_)) => {
+                    self.stopped("breakpoint")?;
+                    break;
+                },
+                Some(Stop::Target) => {
+                    self.stopped(if stepping { "step" } else { "breakpoint" })?;
+                    break;
+                },
+                None => {},
+            }
+        }
+
+        self.flush_output()
+    }
+
+    pub fn run<R: Read + Send + 'static>(&mut self, reader: R) -> io::Result<()> {
+        // Requests are read on their own thread so `pause` can arrive while
+        // the program runs.
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        while !self.done {
+            let request = if self.is_running() {
+                match receiver.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(mpsc::TryRecvError::Empty) => None,
+                    Err(mpsc::TryRecvError::Disconnected) => break,
+                }
+            } else {
+                match receiver.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => break,
+                }
+            };
+
+            if let Some(request) = request {
+                self.handle(&request)?;
+            }
+            if self.is_running() {
+                self.poll()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Serves a single editor session, on stdio or on a local socket such as
+// `:4711`.
+pub fn serve(address: &str, debugger: Debugger) -> io::Result<Debugger> {
+    if address == "stdio" {
+        let mut server = DapServer::new(debugger, io::stdout());
+        server.run(io::stdin())?;
+        return Ok(server.into_debugger());
+    }
+
+    let address = match address.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{}", port),
+        None => address.to_owned(),
+    };
+    let listener = TcpListener::bind(&address)?;
+
+    eprintln!("Waiting for a DAP client on {}.", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    let mut server = DapServer::new(debugger, stream.try_clone()?);
+    server.run(stream)?;
+    Ok(server.into_debugger())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::tests::debugger_with;
+    use crate::lines::LineTable;
+    use std::io::Cursor;
+
+    fn server() -> DapServer<Vec<u8>> {
+        // 0x200: CALL 0x206; JP 0x204; 0x206: LD V3, 0x05; RET
+        let mut debugger = debugger_with(&[0x22, 0x06, 0x12, 0x04, 0x00, 0x00, 0x63, 0x05, 0x00, 0xEE]);
+        debugger.set_line_table(LineTable::parse("200 2 game.s\n202 3 game.s\n206 6 game.s\n208 7 game.s").unwrap());
+        DapServer::new(debugger, Vec::new())
+    }
+
+    // Sends a request and returns everything the server wrote back.
+    fn request(server: &mut DapServer<Vec<u8>>, command: &str, arguments: Value) -> Vec<Value> {
+        server.handle(&json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments })).unwrap();
+        while server.is_running() {
+            server.poll().unwrap();
+        }
+
+        let mut reader = Cursor::new(server.writer.split_off(0));
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn stop_reason(messages: &[Value]) -> Option<&str> {
+        messages.iter().find(|message| message["event"] == "stopped")
+                .and_then(|message| message["body"]["reason"].as_str())
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(&[0xFF, 0x81, 0x00]), "/4EA");
+    }
+
+    #[test]
+    fn test_message_size() {
+        let mut reader = Cursor::new(b"Content-Length: 18446744073709551615\r\n\r\n{}".to_vec());
+        assert!(read_message(&mut reader).is_err());
+
+        let mut reader = Cursor::new(b"Content-Length: 2\r\n\r\n{}".to_vec());
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({})));
+    }
+
+    #[test]
+    fn test_exit() {
+        // CALL 0x200 recurses until the stack overflows and the program stops.
+        let mut server = DapServer::new(debugger_with(&[0x22, 0x00]), Vec::new());
+        request(&mut server, "launch", json!({}));
+
+        let messages = request(&mut server, "configurationDone", json!({}));
+        assert!(messages.iter().any(|message| message["event"] == "exited"));
+        assert!(messages.iter().any(|message| message["event"] == "terminated"));
+        assert!(server.done);
+    }
+
+    #[test]
+    fn test_session() {
+        let mut server = server();
+
+        let messages = request(&mut server, "initialize", json!({}));
+        assert_eq!(messages[0]["body"]["supportsReadMemoryRequest"], true);
+        assert_eq!(messages[1]["event"], "initialized");
+
+        request(&mut server, "launch", json!({ "stopOnEntry": true }));
+        let messages = request(&mut server, "setBreakpoints", json!({
+            "source": { "path": "/src/game.s" },
+            "breakpoints": [{ "line": 5 }, { "line": 9 }],
+        }));
+        let breakpoints = &messages[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[0]["line"], 6);
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        let messages = request(&mut server, "configurationDone", json!({}));
+        assert_eq!(stop_reason(&messages), Some("entry"));
+
+        let messages = request(&mut server, "continue", json!({}));
+        assert_eq!(stop_reason(&messages), Some("breakpoint"));
+
+        let messages = request(&mut server, "stackTrace", json!({ "threadId": 1 }));
+        let frames = &messages[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["line"], 6);
+        assert_eq!(frames[1]["instructionPointerReference"], "0x200");
+        assert_eq!(frames[1]["source"]["path"], "game.s");
+
+        let messages = request(&mut server, "stepIn", json!({ "threadId": 1 }));
+        assert_eq!(stop_reason(&messages), Some("step"));
+
+        let messages = request(&mut server, "variables", json!({ "variablesReference": REGISTERS_REFERENCE }));
+        let variables = messages[0]["body"]["variables"].as_array().unwrap();
+        assert_eq!(variables[3]["value"], "0x05");
+        assert_eq!(variables[17]["name"], "PC");
+        assert_eq!(variables[17]["value"], "0x208");
+
+        let messages = request(&mut server, "variables", json!({ "variablesReference": STACK_REFERENCE }));
+        assert_eq!(messages[0]["body"]["variables"][0]["value"], "0x202");
+
+        let messages = request(&mut server, "stepOut", json!({ "threadId": 1 }));
+        assert_eq!(stop_reason(&messages), Some("step"));
+        assert_eq!(server.debugger.get_current_pc(), 0x202);
+
+        let messages = request(&mut server, "readMemory", json!({ "memoryReference": "0x200", "count": 3 }));
+        assert_eq!(messages[0]["body"]["data"], "IgYS");
+
+        let messages = request(&mut server, "setVariable", json!({ "name": "V3", "value": "0x10" }));
+        assert_eq!(messages[0]["body"]["value"], "0x10");
+
+        let messages = request(&mut server, "evaluate", json!({ "expression": "x/2xb 0x206", "context": "repl" }));
+        assert_eq!(messages[0]["body"]["result"], "0x206: 0x63 0x05");
+
+        let messages = request(&mut server, "pause", json!({}));
+        assert_eq!(stop_reason(&messages), None);
+
+        let messages = request(&mut server, "bogus", json!({}));
+        assert_eq!(messages[0]["success"], false);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut server = server();
+        request(&mut server, "evaluate", json!({ "expression": "break 0x202", "context": "repl" }));
+
+        let messages = request(&mut server, "setBreakpoints", json!({
+            "source": { "path": "game.s" },
+            "breakpoints": [{ "line": 6, "condition": "V3 ==" }, { "line": 7, "condition": "V3 == 5" }],
+        }));
+        let breakpoints = &messages[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], false);
+        assert!(breakpoints[0]["message"].as_str().unwrap().starts_with("Invalid condition"));
+        assert_eq!(breakpoints[1]["verified"], true);
+
+        let messages = request(&mut server, "setInstructionBreakpoints", json!({
+            "breakpoints": [{ "instructionReference": "0x206", "condition": "(V3" }],
+        }));
+        assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], false);
+
+        // The breakpoint set from the console is kept.
+        let messages = request(&mut server, "evaluate", json!({ "expression": "info breakpoints", "context": "repl" }));
+        let result = messages[0]["body"]["result"].as_str().unwrap();
+        assert!(result.contains("0x202"));
+        assert!(result.contains("V3 == 5"));
+        assert!(!result.contains("0x206"));
+    }
+}
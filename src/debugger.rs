@@ -1,38 +1,145 @@
 extern crate rustyline;
 extern crate termion;
 
-use crate::cpu::CPU;
-use crate::memory::{MainMemory, ROM};
-use crate::bus::Bus;
-use crate::specs::{PROGRAM_BEGIN, Address};
+use crate::machine::Machine;
+use crate::specs::{PROGRAM_BEGIN, Address, Byte, Nibble};
 use crate::asm::{decode_instruction, InstructionData};
-use crate::watcher::Watcher;
+use crate::screenshot::Screenshot;
+use crate::expr::{parse_number, Expr, Location};
+use crate::watcher::Message;
+use crate::symbols::SymbolTable;
+use crate::lines::{LineTable, SourceLine};
 
 use rustyline::Editor;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 static PROMPT: &str = "(chip8-debug)";
 
+// Instructions listed by `disas` when neither a count nor the end of the
+// function is known.
+const DISAS_COUNT: usize = 16;
+
+type Condition = (String, Expr);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Watch {
+    Register(Nibble),
+    Memory(Address, Address),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BreakpointKind {
+    Code(Address),
+    Write(Watch),
+    Read(Watch),
+}
+
+// Where `next`, `finish` and `until` stop, besides breakpoints.
+#[derive(Debug, Clone, PartialEq)]
+enum StepTarget {
+    // The return address of a call, reached at the caller's stack depth.
+    Return(Address, Byte),
+    // Anywhere the stack is shallower than the given depth.
+    Frame(Byte),
+    Address(Address),
+    // The start of another source line, calls deeper than the given depth
+    // are stepped over.
+    Line(SourceLine, Option<Byte>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExamineFormat {
+    Hex,
+    Decimal,
+    Binary,
+    Sprite,
+}
+
+struct Breakpoint {
+    number: usize,
+    kind: BreakpointKind,
+    enabled: bool,
+    condition: Option<Condition>,
+    ignore_count: usize,
+    hits: usize,
+}
+
+// Why `advance` stopped execution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    Exited,
+    Breakpoint(usize),
+    Target,
+}
+
+// Messages are buffered so the line-oriented and the full-screen frontends
+// can each show them their own way.
+#[derive(Default)]
+struct Output {
+    lines: RefCell<Vec<String>>,
+}
+
+impl Output {
+    fn say<S: AsRef<str>>(&self, text: S) {
+        let mut lines = self.lines.borrow_mut();
+        lines.extend(text.as_ref().lines().map(str::to_owned));
+    }
+
+    fn take(&self) -> Vec<String> {
+        self.lines.borrow_mut().split_off(0)
+    }
+}
+
 pub struct Debugger {
-    cpu: CPU,
-    bus: Bus,
+    machine: Machine,
     must_exit: bool,
     editor: Editor<()>,
     current_pc: Address,
     need_input: bool,
-    breakpoints: Vec<Address>,
+    target: Option<StepTarget>,
+    breakpoints: Vec<Breakpoint>,
+    breakpoint_count: usize,
     variables: HashMap<String, i32>,
+    screenshot: Screenshot,
+    symbols: SymbolTable,
+    lines: LineTable,
+    sources: HashMap<String, Vec<String>>,
+    output: Output,
+    context_output: bool,
 }
 
 enum DebuggerCommand {
-    Break(Address),
+    Break(BreakpointKind, Option<Condition>),
+    BreakLine(String, usize, Option<Condition>),
+    InfoBreakpoints,
+    Delete(Option<usize>),
+    Enable(usize, bool),
+    SetCondition(usize, Option<Condition>),
+    Ignore(usize, usize),
     Set(String, i32),
+    Assign(Location, Expr),
+    Poke(Address, Vec<Byte>),
+    Examine(usize, ExamineFormat, Expr),
+    Disassemble(Option<String>, Option<usize>),
     Continue,
     Run,
     Status,
+    Step,
     Next,
+    StepInstruction,
+    NextInstruction,
+    Finish,
+    Until(Address),
     Ctx,
     Screen,
+    Screenshot(PathBuf),
+    SymbolFile(PathBuf),
+    Backtrace,
+    Frame(usize),
     Empty,
     Quit,
     Dump,
@@ -54,99 +161,604 @@ impl Debugger {
         }
     }
 
+    fn parse_address(tok: Option<&str>) -> Result<Address, String> {
+        let tok = tok.ok_or_else(|| "Missing address".to_owned())?;
+        u16::from_str_radix(tok.trim_start_matches("0x"), 16)
+            .map_err(|_| "Error while parsing address.".to_owned())
+    }
+
+    fn parse_watch(tok: Option<&str>) -> Result<Watch, String> {
+        let tok = tok.ok_or_else(|| "Missing register or memory range".to_owned())?;
+
+        if let Some(Location::Register(index)) = Location::parse(tok) {
+            return Ok(Watch::Register(index));
+        }
+
+        let range = tok.strip_prefix('[').and_then(|tok| tok.strip_suffix(']'))
+                       .ok_or_else(|| format!("Expected a register or [address]: {}", tok))?;
+        let mut bounds = range.splitn(2, "..");
+        let start = Debugger::parse_address(bounds.next())?;
+        let end = match bounds.next() {
+            Some(end) => Debugger::parse_address(Some(end))?,
+            None => start,
+        };
+
+        if end < start {
+            return Err("Empty memory range".to_owned());
+        }
+        Ok(Watch::Memory(start, end))
+    }
+
+    // Parses the `/16xb` part of `x/16xb`: a count, a format letter and an
+    // optional unit size, which can only be bytes.
+    fn parse_examine_format(text: &str) -> Result<(usize, ExamineFormat), String> {
+        let text = match text.strip_prefix('/') {
+            Some(text) => text,
+            None => return Ok((1, ExamineFormat::Hex)),
+        };
+        let digits = text.find(|chr: char| !chr.is_ascii_digit()).unwrap_or(text.len());
+        let count = match &text[..digits] {
+            "" => 1,
+            count => Debugger::parse_count(Some(count))?,
+        };
+
+        let mut format = ExamineFormat::Hex;
+        for chr in text[digits..].chars() {
+            format = match chr {
+                'x' => ExamineFormat::Hex,
+                'd' => ExamineFormat::Decimal,
+                't' => ExamineFormat::Binary,
+                's' => ExamineFormat::Sprite,
+                'b' => continue,
+                _ => return Err(format!("Invalid format letter: {}", chr)),
+            };
+        }
+
+        Ok((count, format))
+    }
+
+    fn parse_count(tok: Option<&str>) -> Result<usize, String> {
+        let tok = tok.ok_or_else(|| "Missing number".to_owned())?;
+        tok.parse().map_err(|_| format!("Invalid number: {}", tok))
+    }
+
+    fn parse_condition(text: &str) -> Result<Option<Condition>, String> {
+        if text.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some((text.to_owned(), Expr::parse(text)?)))
+    }
+
     fn parse_input(line: &str) -> Result<DebuggerCommand, String> {
         use DebuggerCommand::*;
 
-        let mut tokens = line.split(" ").into_iter();
+        let mut tokens = line.split_whitespace();
 
         if let Some(tok) = tokens.next() {
             Ok(match tok {
                 "ctx" => Ctx,
                 "c" | "continue" => Continue,
+                "step" | "s" => Step,
                 "next" | "n" => Next,
+                "stepi" | "si" => StepInstruction,
+                "nexti" | "ni" => NextInstruction,
+                "finish" | "fin" => Finish,
+                "until" | "u" => Until(Debugger::parse_address(tokens.next())?),
                 "run" | "r" => Run,
                 "dump" => Dump,
                 "screen" => Screen,
                 "status" => Status,
                 "quit" | "exit" | "q" => Quit,
                 "set" => {
+                    let rest = tokens.collect::<Vec<&str>>().join(" ");
+                    let (key, value) = match rest.find('=') {
+                        Some(idx) => (rest[..idx].trim(), rest[idx + 1..].trim()),
+                        None => rest.split_once(' ').unwrap_or((&rest, "")),
+                    };
+
+                    if key.is_empty() {
+                        return Err("Missing key after set".to_owned())
+                    } else if value.is_empty() {
+                        return Err("Missing value after key.".to_owned())
+                    }
+
+                    match Location::parse(key) {
+                        Some(location) => Assign(location, Expr::parse(value)?),
+                        None => match value.parse::<i32>() {
+                            Ok(val) => Set(key.to_owned(), val),
+                            Err(_) => return Err("Unable to parse value.".to_owned()),
+                        },
+                    }
+                },
+                "disas" | "disassemble" => {
+                    let target = tokens.next().map(str::to_owned);
+                    match tokens.next() {
+                        Some(tok) => Disassemble(target, Some(Debugger::parse_count(Some(tok))?)),
+                        None => Disassemble(target, None),
+                    }
+                },
+                "poke" => {
+                    let address = Debugger::parse_address(tokens.next())?;
+                    let bytes = tokens.map(|tok| match parse_number(tok) {
+                        Ok(value) if (0..=0xFF).contains(&value) => Ok(value as Byte),
+                        _ => Err(format!("Invalid byte: {}", tok)),
+                    }).collect::<Result<Vec<Byte>, String>>()?;
+
+                    if bytes.is_empty() {
+                        return Err("Missing bytes after address".to_owned())
+                    }
+                    Poke(address, bytes)
+                },
+                examine if examine == "x" || examine.starts_with("x/") => {
+                    let (count, format) = Debugger::parse_examine_format(&examine[1..])?;
+                    let rest = tokens.collect::<Vec<&str>>().join(" ");
+                    if rest.is_empty() {
+                        return Err("Missing address after x".to_owned())
+                    }
+                    Examine(count, format, Expr::parse(&rest)?)
+                },
+                "screenshot" => {
                     if let Some(tok) = tokens.next() {
-                        let key = tok.to_owned();
-                        if let Some(tok) = tokens.next() {
-                            let value = i32::from_str_radix(tok, 10);
-                            if let Ok(val) = value {
-                                Set(key, val)
-                            } else {
-                                return Err("Unable to parse value.".to_owned())
-                            }
-                        } else {
-                            return Err("Missing value after key.".to_owned())
-                        }
+                        Screenshot(PathBuf::from(tok))
                     } else {
-                        return Err("Missing key after set".to_owned())
+                        return Err("Missing file after screenshot".to_owned())
                     }
                 },
-                "break" | "b" => {
+                "symbol-file" => {
                     if let Some(tok) = tokens.next() {
-                        let processed = tok.trim_start_matches("0x");
-                        let parsed = u16::from_str_radix(processed, 16);
-                        if let Ok(addr) = parsed {
-                            Break(addr)
-                        } else {
-                            return Err("Error while parsing address.".to_owned())
-                        }
+                        SymbolFile(PathBuf::from(tok))
                     } else {
-                        return Err("Missing argument after break".to_owned())
+                        return Err("Missing file after symbol-file".to_owned())
+                    }
+                },
+                "bt" | "backtrace" | "where" => Backtrace,
+                "frame" | "f" => match tokens.next() {
+                    Some(tok) => Frame(Debugger::parse_count(Some(tok))?),
+                    None => Frame(0),
+                },
+                "break" | "b" | "watch" | "rwatch" => {
+                    let kind = match tok {
+                        "watch" => BreakpointKind::Write(Debugger::parse_watch(tokens.next())?),
+                        "rwatch" => match Debugger::parse_watch(tokens.next())? {
+                            Watch::Register(_) => {
+                                return Err("Only memory can be watched for reads".to_owned())
+                            },
+                            watch => BreakpointKind::Read(watch),
+                        },
+                        _ => match tokens.next() {
+                            Some(location) if location.contains(':') => {
+                                let (file, line) = location.rsplit_once(':').unwrap_or_default();
+                                let line = Debugger::parse_count(Some(line))?;
+                                let rest: Vec<&str> = tokens.collect();
+                                return match rest.split_first() {
+                                    Some((&"if", condition)) => {
+                                        Ok(BreakLine(file.to_owned(), line,
+                                                     Debugger::parse_condition(&condition.join(" "))?))
+                                    },
+                                    Some(_) => Err(format!("Expected `if` after {}", location)),
+                                    None => Ok(BreakLine(file.to_owned(), line, None)),
+                                };
+                            },
+                            location => BreakpointKind::Code(Debugger::parse_address(location)?),
+                        },
+                    };
+                    let rest: Vec<&str> = tokens.collect();
+                    match rest.split_first() {
+                        Some((&"if", condition)) => {
+                            Break(kind, Debugger::parse_condition(&condition.join(" "))?)
+                        },
+                        Some(_) => return Err(format!("Expected `if` after {}", kind)),
+                        None => Break(kind, None),
                     }
                 },
-                "" => Empty,
+                "info" | "i" => match tokens.next() {
+                    Some("breakpoints") | Some("break") | Some("b") => InfoBreakpoints,
+                    _ => return Err("Usage: info breakpoints".to_owned()),
+                },
+                "delete" | "d" => match tokens.next() {
+                    Some(tok) => Delete(Some(Debugger::parse_count(Some(tok))?)),
+                    None => Delete(None),
+                },
+                "enable" => Enable(Debugger::parse_count(tokens.next())?, true),
+                "disable" => Enable(Debugger::parse_count(tokens.next())?, false),
+                "condition" => {
+                    let number = Debugger::parse_count(tokens.next())?;
+                    let rest: Vec<&str> = tokens.collect();
+                    SetCondition(number, Debugger::parse_condition(&rest.join(" "))?)
+                },
+                "ignore" => {
+                    let number = Debugger::parse_count(tokens.next())?;
+                    Ignore(number, Debugger::parse_count(tokens.next())?)
+                },
                 _ => return Err(format!("Unknown command: {}", tok)),
             })
         } else {
-            Err("Unknown error".to_owned())
+            Ok(Empty)
         }
     }
 
-    fn get_execution_context(&self) -> Vec<(Address, InstructionData)> {
+    fn get_execution_context(&self, center: Address) -> Vec<(Address, InstructionData)> {
         let mut instrs: Vec<(Address, InstructionData)> = Vec::new();
         let size = self.variables["context_span"] as u16;
 
-        let mut addr = if size * 2 > self.current_pc {
-            0
-        } else {
-            self.current_pc - (size * 2)
-        };
-        while addr <= self.current_pc + (size * 2) {
-            if addr < (PROGRAM_BEGIN as u16) {
-                addr += 2;
-                continue;
+        let mut addr = center.saturating_sub(size.saturating_mul(2));
+        let end = center.saturating_add(size.saturating_mul(2));
+        while addr <= end {
+            if addr >= (PROGRAM_BEGIN as u16) {
+                let instr = self.machine.get_bus().read_instruction(addr);
+                instrs.push((addr, decode_instruction(instr)));
             }
 
-            let instr = self.bus.read_instruction(addr);
-            instrs.push((addr, decode_instruction(instr)));
-
-            addr += 2;
+            // Stops at the end of the address space.
+            match addr.checked_add(2) {
+                Some(next) => addr = next,
+                None => break,
+            }
         }
 
         instrs
     }
 
     fn show_context(&self) {
+        if self.context_output {
+            self.show_context_at(self.current_pc);
+        }
+    }
+
+    // Shows source lines when the address has some, instructions otherwise.
+    fn show_context_at(&self, center: Address) {
+        let source = self.lines.lookup(center);
+        if !source.is_some_and(|source| self.show_source(source)) {
+            self.show_listing(&self.get_execution_context(center), center);
+        }
+    }
+
+    fn line_marker(&self, source: &SourceLine) -> &'static str {
+        self.breakpoints.iter()
+            .find(|breakpoint| match breakpoint.kind {
+                BreakpointKind::Code(address) => self.lines.lookup(address) == Some(source),
+                _ => false,
+            })
+            .map_or("", |breakpoint| if breakpoint.enabled { "*" } else { "o" })
+    }
+
+    fn show_source(&self, current: &SourceLine) -> bool {
         use termion::{color, style};
 
-        let instrs = self.get_execution_context();
-        for (addr, instr) in instrs {
-            if addr == self.current_pc {
-                print!("{:<4}{}", "->", color::Fg(color::Green));
+        // Stale or mismatched sources are no use either.
+        let text = match self.sources.get(&current.file) {
+            Some(text) if current.line <= text.len() => text,
+            _ => return false,
+        };
+        let span = self.variables["context_span"] as usize;
+        let first = current.line.saturating_sub(span).max(1);
+        let last = (current.line + span).min(text.len());
+
+        for line in first..=last {
+            let source = SourceLine { file: current.file.clone(), line };
+            let mut output = format!("{:<2}", self.line_marker(&source));
+            if line == current.line {
+                output += &format!("{:<2}{}", "->", color::Fg(color::Green));
             } else {
-                print!("{:<4}", "");
+                output += &format!("{:<2}", "");
             }
 
-            println!("{:#05X}   {}{}", addr, instr, style::Reset);
+            self.output.say(format!("{}{:>4}  {}{}", output, line, text[line - 1], style::Reset));
         }
+        true
     }
 
-    fn process_input(&mut self, input: &str) {
+    // Decodes instructions from the current memory contents, so code patched
+    // at runtime is listed as it will execute.
+    pub fn disassemble(&self, start: Address, count: usize) -> Vec<(Address, InstructionData)> {
+        let size = self.machine.get_bus().get_ram().size();
+
+        (0..count).map(|idx| start as usize + idx * 2)
+                  .take_while(|addr| addr + 1 < size)
+                  .map(|addr| {
+                      let instr = self.machine.get_bus().read_instruction(addr as Address);
+                      (addr as Address, decode_instruction(instr))
+                  })
+                  .collect()
+    }
+
+    // Resolves the arguments of `disas` to a start address and a number of
+    // instructions. A label without a count lists up to the next symbol.
+    fn disassembly_range(&self, target: Option<&str>, count: Option<usize>) -> Result<(Address, usize), String> {
+        let start = match target {
+            None => self.current_pc,
+            Some(target) => match self.symbols.address_of(target) {
+                Some(address) => address,
+                None => Debugger::parse_address(Some(target))
+                    .map_err(|_| format!("No symbol or address: {}", target))?,
+            },
+        };
+
+        let count = count.unwrap_or_else(|| match (target, self.symbols.next_symbol(start)) {
+            (Some(target), Some(end)) if self.symbols.address_of(target).is_some() => {
+                ((end - start) as usize).div_ceil(2)
+            },
+            _ => DISAS_COUNT,
+        });
+
+        Ok((start, count))
+    }
+
+    fn show_listing(&self, instrs: &[(Address, InstructionData)], highlight: Address) {
+        use termion::{color, style};
+
+        for &(addr, instr) in instrs {
+            if let Some((name, 0)) = self.symbols.lookup(addr) {
+                self.output.say(format!("{}:", name));
+            }
+
+            let mut line = format!("{:<2}", self.breakpoint_marker(addr));
+            if addr == highlight {
+                line += &format!("{:<2}{}", "->", color::Fg(color::Green));
+            } else {
+                line += &format!("{:<2}", "");
+            }
+
+            self.output.say(format!("{}{:#05X}   {}{}", line, addr, instr, style::Reset));
+        }
+    }
+
+    // `*` marks an enabled breakpoint, `o` a disabled one.
+    pub fn breakpoint_marker(&self, address: Address) -> &'static str {
+        self.breakpoints.iter()
+            .find(|breakpoint| breakpoint.kind == BreakpointKind::Code(address))
+            .map_or("", |breakpoint| if breakpoint.enabled { "*" } else { "o" })
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    // Frame 0 is the current instruction, the others are the call sites of
+    // the subroutines in progress, innermost first.
+    pub fn frames(&self) -> Vec<Address> {
+        let stack = self.machine.get_cpu().get_stack();
+        let calls = stack.iter().rev().map(|ret| ret.wrapping_sub(2));
+
+        std::iter::once(self.current_pc).chain(calls).collect()
+    }
+
+    fn describe_frame(&self, level: usize, address: Address) -> String {
+        let mut line = format!("#{:<3}{:#05X}", level, address);
+        if let Some(name) = self.symbols.describe(address) {
+            line += &format!(" in {}", name);
+        }
+        if level > 0 {
+            line += &format!(", returns to {:#05X}", address.wrapping_add(2));
+        }
+        line
+    }
+
+    fn show_backtrace(&self) {
+        for (level, address) in self.frames().into_iter().enumerate() {
+            self.output.say(self.describe_frame(level, address));
+        }
+    }
+
+    fn show_frame(&self, level: usize) {
+        match self.frames().get(level) {
+            Some(&address) => {
+                self.output.say(self.describe_frame(level, address));
+                self.show_context_at(address);
+            },
+            None => self.output.say(format!("No frame at level {}.", level)),
+        }
+    }
+
+    fn examine(&self, count: usize, format: ExamineFormat, address: &Expr) -> Result<String, String> {
+        let start = address.eval(&self.machine)?;
+        let ram = self.machine.get_bus().get_ram();
+        if start < 0 || start as usize >= ram.size() {
+            return Err(format!("Address out of memory: {:#X}", start));
+        }
+
+        let start = start as usize;
+        let end = ram.size().min(start.saturating_add(count));
+        let bytes = ram.slice(start as Address, (end - start) as Address);
+        let per_line = match format {
+            ExamineFormat::Hex | ExamineFormat::Decimal => 8,
+            ExamineFormat::Binary => 4,
+            ExamineFormat::Sprite => 1,
+        };
+
+        let mut output = String::new();
+        for (line, chunk) in bytes.chunks(per_line).enumerate() {
+            output += &format!("{:#05X}:", start + line * per_line);
+            for byte in chunk {
+                output += &match format {
+                    ExamineFormat::Hex => format!(" {:#04X}", byte),
+                    ExamineFormat::Decimal => format!(" {:>3}", byte),
+                    ExamineFormat::Binary => format!(" {:08b}", byte),
+                    ExamineFormat::Sprite => {
+                        let pixels: String = (0..8).rev().map(|bit| {
+                            if byte & (1 << bit) != 0 { '▉' } else { '.' }
+                        }).collect();
+                        format!(" {}", pixels)
+                    },
+                };
+            }
+            output += "\n";
+        }
+
+        Ok(output)
+    }
+
+    fn breakpoint(&mut self, number: usize) -> Option<&mut Breakpoint> {
+        let breakpoint = self.breakpoints.iter_mut().find(|breakpoint| breakpoint.number == number);
+        if breakpoint.is_none() {
+            self.output.say(format!("No breakpoint number {}.", number));
+        }
+        breakpoint
+    }
+
+    fn show_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            self.output.say("No breakpoints.");
+            return;
+        }
+
+        self.output.say(format!("{:<5}{:<5}{:<30}{:<6}Condition", "Num", "Enb", "What", "Hits"));
+        for breakpoint in &self.breakpoints {
+            let mut line = format!("{:<5}{:<5}{:<30}{:<6}", breakpoint.number,
+                                   if breakpoint.enabled { "y" } else { "n" },
+                                   breakpoint.kind.to_string(), breakpoint.hits);
+            if let Some((ref text, _)) = breakpoint.condition {
+                line += text;
+            }
+            if breakpoint.ignore_count > 0 {
+                line += &format!(" (ignore next {} hits)", breakpoint.ignore_count);
+            }
+            self.output.say(line);
+        }
+    }
+
+    fn watch_hit(watch: Watch, read: bool, log: &[Message]) -> Option<String> {
+        let in_range = |address: Address| match watch {
+            Watch::Memory(start, end) => address >= start && address <= end,
+            Watch::Register(_) => false,
+        };
+
+        log.iter().find_map(|msg| match (msg, watch) {
+            (&Message::RegisterChange { id, old, new }, Watch::Register(index)) if !read && id == index => {
+                Some(format!("V{:X}: {:#04X} => {:#04X}", id, old, new))
+            },
+            (&Message::MemoryWrite { address, old, new }, _) if !read && in_range(address) => {
+                Some(format!("[{:#05X}]: {:#04X} => {:#04X}", address, old, new))
+            },
+            (&Message::MemoryRead { address, value }, _) if read && in_range(address) => {
+                Some(format!("[{:#05X}] read: {:#04X}", address, value))
+            },
+            _ => None,
+        })
+    }
+
+    // Returns the breakpoint execution should stop on, along with a report of
+    // the access for watchpoints. `log` holds what the last instruction did.
+    fn check_breakpoints(&mut self, log: &[Message]) -> Option<(usize, Option<String>)> {
+        let pc = self.current_pc;
+        let machine = &self.machine;
+
+        for breakpoint in self.breakpoints.iter_mut() {
+            if !breakpoint.enabled {
+                continue;
+            }
+
+            let report = match breakpoint.kind {
+                BreakpointKind::Code(address) if address == pc => None,
+                BreakpointKind::Code(_) => continue,
+                BreakpointKind::Write(watch) => match Debugger::watch_hit(watch, false, log) {
+                    Some(report) => Some(report),
+                    None => continue,
+                },
+                BreakpointKind::Read(watch) => match Debugger::watch_hit(watch, true, log) {
+                    Some(report) => Some(report),
+                    None => continue,
+                },
+            };
+
+            if let Some((ref text, ref condition)) = breakpoint.condition {
+                match condition.eval(machine) {
+                    Ok(0) => continue,
+                    Ok(_) => {},
+                    Err(err) => {
+                        self.output.say(format!("Error in condition of breakpoint {} `{}`: {}",
+                                                breakpoint.number, text, err));
+                        return Some((breakpoint.number, report));
+                    },
+                }
+            }
+
+            breakpoint.hits += 1;
+            if breakpoint.ignore_count > 0 {
+                breakpoint.ignore_count -= 1;
+                continue;
+            }
+
+            return Some((breakpoint.number, report));
+        }
+
+        None
+    }
+
+    // Accesses are only logged while watchpoints are set, logging every
+    // instruction would slow down execution for nothing.
+    fn update_logging(&self) {
+        let watching = self.breakpoints.iter().any(|breakpoint| {
+            breakpoint.enabled && !matches!(breakpoint.kind, BreakpointKind::Code(_))
+        });
+        self.machine.get_watcher().set_logging(watching);
+    }
+
+    fn step(&mut self) -> Option<(usize, Option<String>)> {
+        let executed = self.current_pc;
+        self.current_pc = self.machine.step();
+        let log = self.machine.get_watcher().take_log();
+        let stop = self.check_breakpoints(&log);
+
+        if let Some((number, Some(ref report))) = stop {
+            self.output.say(format!("Watchpoint {}: {}", number, report));
+            for msg in &log {
+                if let Message::Execute { instr, .. } = msg {
+                    self.output.say(format!("    by {:#05X}: {}", executed, instr));
+                }
+            }
+        }
+
+        stop
+    }
+
+    // Steps over calls, a single instruction otherwise.
+    fn next_instruction(&mut self) {
+        let instr = self.machine.get_bus().read_instruction(self.current_pc);
+        if let InstructionData::Call(_) = decode_instruction(instr) {
+            let depth = self.machine.get_cpu().get_sp();
+            self.resume(StepTarget::Return(self.current_pc.wrapping_add(2), depth));
+        } else {
+            self.step();
+            self.show_context();
+        }
+    }
+
+    fn add_breakpoint(&mut self, kind: BreakpointKind, condition: Option<Condition>) {
+        self.breakpoint_count += 1;
+        self.breakpoints.push(Breakpoint {
+            number: self.breakpoint_count,
+            kind,
+            enabled: true,
+            condition,
+            ignore_count: 0,
+            hits: 0,
+        });
+    }
+
+    fn resume(&mut self, target: StepTarget) {
+        self.target = Some(target);
+        self.need_input = false;
+    }
+
+    fn target_reached(&self) -> bool {
+        let depth = self.machine.get_cpu().get_sp();
+        match self.target {
+            Some(StepTarget::Line(ref start, Some(caller))) if depth != caller => depth < caller,
+            Some(StepTarget::Line(ref start, _)) => {
+                self.lines.lookup(self.current_pc).is_some_and(|source| source != start)
+            },
+            Some(StepTarget::Return(address, caller)) => {
+                (self.current_pc == address && depth <= caller) || depth < caller
+            },
+            Some(StepTarget::Frame(frame)) => depth < frame,
+            Some(StepTarget::Address(address)) => self.current_pc == address,
+            None => false,
+        }
+    }
+
+    pub fn process_input(&mut self, input: &str) {
         use DebuggerCommand::*;
 
         let res = Debugger::parse_input(input);
@@ -154,71 +766,280 @@ impl Debugger {
             Ok(cmd) => {
                 match cmd {
                     Empty => {},
-                    Status => println!("{}", self.cpu),
+                    Status => self.output.say(format!("{}", self.machine.get_cpu())),
                     Run => {
                         self.need_input = false;
-                        self.cpu.reset();
+                        self.target = None;
+                        self.machine.reset();
                     },
                     Ctx => self.show_context(),
-                    Dump => println!("{}", self.bus.get_ram()),
-                    Next => {
-                        self.current_pc = self.cpu.tick(&mut self.bus);
+                    Dump => self.output.say(format!("{}", self.machine.get_bus().get_ram())),
+                    Step => match self.lines.lookup(self.current_pc) {
+                        Some(source) => self.resume(StepTarget::Line(source.clone(), None)),
+                        None => {
+                            self.step();
+                            self.show_context();
+                        },
+                    },
+                    Next => match self.lines.lookup(self.current_pc) {
+                        Some(source) => {
+                            let depth = self.machine.get_cpu().get_sp();
+                            self.resume(StepTarget::Line(source.clone(), Some(depth)));
+                        },
+                        None => self.next_instruction(),
+                    },
+                    StepInstruction => {
+                        self.step();
                         self.show_context();
                     },
-                    Screen => println!("{}", self.bus.get_frame_buffer()),
-                    Continue => self.need_input = false,
+                    NextInstruction => self.next_instruction(),
+                    Finish => {
+                        let depth = self.machine.get_cpu().get_sp();
+                        if depth == 0 {
+                            self.output.say("\"finish\" not meaningful in the outermost frame.");
+                        } else {
+                            self.resume(StepTarget::Frame(depth));
+                        }
+                    },
+                    Until(address) => self.resume(StepTarget::Address(address)),
+                    Screen => self.output.say(format!("{}", self.machine.get_bus().get_frame_buffer())),
+                    Screenshot(path) => {
+                        let frame_buffer = self.machine.get_bus().get_frame_buffer();
+                        match self.screenshot.save(frame_buffer, &path) {
+                            Ok(()) => self.output.say(format!("Screenshot saved to {}.", path.display())),
+                            Err(err) => self.output.say(format!("Unable to save screenshot: {}", err)),
+                        }
+                    },
+                    SymbolFile(path) => match SymbolTable::load(&path) {
+                        Ok(symbols) => {
+                            self.output.say(format!("Loaded symbols from {}.", path.display()));
+                            self.symbols = symbols;
+                        },
+                        Err(err) => self.output.say(format!("Unable to load symbols: {}", err)),
+                    },
+                    Backtrace => self.show_backtrace(),
+                    Frame(level) => self.show_frame(level),
+                    Continue => {
+                        self.need_input = false;
+                        self.target = None;
+                    },
                     Quit => {
                         self.must_exit = true;
                     },
-                    Break(addr) => {
-                        println!("Setting breakpoint at {:#05x}.", addr);
-                        self.breakpoints.push(addr);
+                    Break(kind, condition) => {
+                        self.add_breakpoint(kind, condition);
+                        self.output.say(format!("Breakpoint {}: {}.", self.breakpoint_count, kind));
+                    },
+                    BreakLine(file, line, condition) => match self.lines.address_of(&file, line) {
+                        Some((address, line)) => {
+                            self.add_breakpoint(BreakpointKind::Code(address), condition);
+                            self.output.say(format!("Breakpoint {} at {:#05X}: {}:{}.",
+                                                    self.breakpoint_count, address, file, line));
+                        },
+                        None => self.output.say(format!("No code at {}:{}.", file, line)),
+                    },
+                    InfoBreakpoints => self.show_breakpoints(),
+                    Delete(None) => self.breakpoints.clear(),
+                    Delete(Some(number)) => {
+                        if self.breakpoint(number).is_some() {
+                            self.breakpoints.retain(|breakpoint| breakpoint.number != number);
+                        }
+                    },
+                    Enable(number, enabled) => {
+                        if let Some(breakpoint) = self.breakpoint(number) {
+                            breakpoint.enabled = enabled;
+                        }
+                    },
+                    SetCondition(number, condition) => {
+                        if let Some(breakpoint) = self.breakpoint(number) {
+                            breakpoint.condition = condition;
+                        }
+                    },
+                    Ignore(number, count) => {
+                        if let Some(breakpoint) = self.breakpoint(number) {
+                            breakpoint.ignore_count = count;
+                            self.output.say(format!("Will ignore next {} crossings of breakpoint {}.", count, number));
+                        }
                     },
                     Set(key, value) => {
                         self.variables.insert(key, value);
                     },
+                    Assign(location, value) => {
+                        let result = value.eval(&self.machine)
+                                          .and_then(|value| location.write(&mut self.machine, value));
+                        match result {
+                            Ok(()) => self.current_pc = self.machine.get_cpu().get_pc(),
+                            Err(err) => self.output.say(err),
+                        }
+                    },
+                    Poke(address, bytes) => {
+                        if address as usize + bytes.len() > self.machine.get_bus().get_ram().size() {
+                            self.output.say(format!("Address out of memory: {:#X}", address));
+                        } else {
+                            self.machine.get_bus_mut().write_bytes(address, &bytes);
+                        }
+                    },
+                    Disassemble(target, count) => {
+                        match self.disassembly_range(target.as_deref(), count) {
+                            Ok((start, count)) => {
+                                self.show_listing(&self.disassemble(start, count), self.current_pc)
+                            },
+                            Err(err) => self.output.say(err),
+                        }
+                    },
+                    Examine(count, format, address) => {
+                        match self.examine(count, format, &address) {
+                            Ok(output) => self.output.say(output),
+                            Err(err) => self.output.say(err),
+                        }
+                    },
                 }
             },
             Err(err) => {
-                println!("{}", err);
+                self.output.say(err);
             }
         }
+
+        self.update_logging();
     }
 
-    pub fn new(rom: ROM, watcher: Watcher) -> Self {
-        let mem = MainMemory::with_rom(rom);
+    pub fn new(machine: Machine, screenshot: Screenshot) -> Self {
         let mut variables = HashMap::new();
 
         variables.insert("context_span".to_owned(), 2);
 
         Debugger {
-            cpu: CPU::new(watcher),
+            machine,
             editor: Editor::<()>::new(),
             must_exit: false,
-            bus: Bus::new(mem),
             need_input: true,
+            target: None,
             current_pc: PROGRAM_BEGIN as u16,
             breakpoints: Vec::new(),
-            variables
+            breakpoint_count: 0,
+            variables,
+            screenshot,
+            symbols: SymbolTable::new(),
+            lines: LineTable::new(),
+            sources: HashMap::new(),
+            output: Output::default(),
+            context_output: true,
+        }
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    // Executes one instruction of a `continue`, `next`, `finish` or `until`.
+    pub fn advance(&mut self) -> Option<Stop> {
+        let hit = self.step();
+        let stop = if self.machine.has_exited() {
+            self.output.say("Program exited.");
+            Some(Stop::Exited)
+        } else if let Some((number, report)) = hit {
+            self.show_context();
+            if report.is_none() {
+                self.output.say(format!("Stopped on breakpoint {} at {:#05X}.", number, self.current_pc));
+            }
+            Some(Stop::Breakpoint(number))
+        } else if self.target_reached() {
+            self.show_context();
+            Some(Stop::Target)
+        } else {
+            None
+        };
+
+        if stop.is_some() {
+            self.need_input = true;
+            self.target = None;
+        }
+        stop
+    }
+
+    pub fn interrupt(&mut self) {
+        if !self.need_input {
+            self.need_input = true;
+            self.target = None;
+            self.output.say(format!("Interrupted at {:#05X}.", self.current_pc));
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.need_input
+    }
+
+    // Whether execution stops at a `next`, `finish` or `until` target.
+    pub fn has_target(&self) -> bool {
+        self.target.is_some()
+    }
+
+    pub fn must_exit(&self) -> bool {
+        self.must_exit
+    }
+
+    pub fn get_machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn get_current_pc(&self) -> Address {
+        self.current_pc
+    }
+
+    pub fn get_symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    // The number given to the last breakpoint set.
+    pub fn get_breakpoint_count(&self) -> usize {
+        self.breakpoint_count
+    }
+
+    pub fn set_line_table(&mut self, lines: LineTable) {
+        self.lines = lines;
+    }
+
+    // Reads the files of the line table, relative to `base`. Missing files
+    // are shown as instructions.
+    pub fn load_sources(&mut self, base: &Path) {
+        for file in self.lines.files() {
+            if let Ok(text) = fs::read_to_string(base.join(file)) {
+                self.sources.insert(file.to_owned(), text.lines().map(str::to_owned).collect());
+            }
+        }
+    }
+
+    pub fn get_line_table(&self) -> &LineTable {
+        &self.lines
+    }
+
+    // The full-screen debugger has its own disassembly pane.
+    pub fn set_context_output(&mut self, enabled: bool) {
+        self.context_output = enabled;
+    }
+
+    pub fn take_output(&self) -> Vec<String> {
+        self.output.take()
+    }
+
+    fn flush_output(&self) {
+        for line in self.output.take() {
+            println!("{}", line);
         }
     }
 
     pub fn run(&mut self) {
         self.show_context();
+        self.flush_output();
         loop {
             if self.need_input {
                 if let Ok(line) = self.prompt() {
                     self.process_input(&line);
                 }
             } else {
-                self.current_pc = self.cpu.tick(&mut self.bus);
-                if self.breakpoints.contains(&self.current_pc) {
-                    self.need_input = true;
-                    self.show_context();
-                    println!("Stopped on breakpoint at {:#05X}.", self.current_pc);
-                }
-
+                self.advance();
             }
+            self.flush_output();
 
             if self.must_exit {
                 break
@@ -226,3 +1047,309 @@ impl Debugger {
         }
     }
 }
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watch::Register(index) => write!(f, "V{:X}", index),
+            Watch::Memory(start, end) if start == end => write!(f, "[{:#05X}]", start),
+            Watch::Memory(start, end) => write!(f, "[{:#05X}..{:#05X}]", start, end),
+        }
+    }
+}
+
+impl fmt::Display for BreakpointKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakpointKind::Code(address) => write!(f, "breakpoint at {:#05X}", address),
+            BreakpointKind::Write(watch) => write!(f, "watch {}", watch),
+            BreakpointKind::Read(watch) => write!(f, "rwatch {}", watch),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::debugger_tui::strip_escapes;
+    use crate::memory::ROM;
+    use crate::palette::Palette;
+    use crate::specs::{Byte, Platform};
+    use crate::watcher::Watcher;
+
+    // 0x200: CALL 0x206; JP 0x204; 0x206: CALL 0x20A; RET; 0x20A: LD V0, 1; RET
+    const NESTED_CALLS: [Byte; 14] = [
+        0x22, 0x06, 0x12, 0x04, 0x00, 0x00, 0x22, 0x0A, 0x00, 0xEE, 0x60, 0x01, 0x00, 0xEE,
+    ];
+
+    pub(crate) fn debugger_with(rom: &[Byte]) -> Debugger {
+        let machine = Machine::new(ROM::from(rom.to_vec()), Watcher::new(), 0, Platform::Chip8).unwrap();
+        Debugger::new(machine, Screenshot::new(Palette::default(), 1))
+    }
+
+    fn debugger() -> Debugger {
+        // LD V3, 0x00; ADD V3, 0x01; JP 0x202
+        debugger_with(&[0x63, 0x00, 0x73, 0x01, 0x12, 0x02])
+    }
+
+    // Runs until a breakpoint is hit and returns its number.
+    fn run_to_breakpoint(debugger: &mut Debugger) -> usize {
+        loop {
+            if let Some((number, _)) = debugger.step() {
+                return number;
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_breakpoints() {
+        assert!(matches!(Debugger::parse_input("break 0x2A0"), Ok(DebuggerCommand::Break(BreakpointKind::Code(0x2A0), None))));
+        assert!(matches!(Debugger::parse_input("b 2a0 if V3 == 5 && I > 0x300"),
+                         Ok(DebuggerCommand::Break(BreakpointKind::Code(0x2A0), Some(_)))));
+        assert!(matches!(Debugger::parse_input("info breakpoints"), Ok(DebuggerCommand::InfoBreakpoints)));
+        assert!(matches!(Debugger::parse_input("delete"), Ok(DebuggerCommand::Delete(None))));
+        assert!(matches!(Debugger::parse_input("disable 2"), Ok(DebuggerCommand::Enable(2, false))));
+        assert!(matches!(Debugger::parse_input(""), Ok(DebuggerCommand::Empty)));
+        assert!(Debugger::parse_input("break 0x2A0 if V3 ==").is_err());
+        assert!(Debugger::parse_input("break 0x2A0 when V3").is_err());
+    }
+
+    #[test]
+    fn test_conditions_and_ignore_counts() {
+        let mut debugger = debugger();
+        debugger.process_input("break 0x202 if V3 == 3");
+        assert_eq!(run_to_breakpoint(&mut debugger), 1);
+        assert_eq!(debugger.machine.get_cpu().get_reg(3), 3);
+
+        debugger.process_input("condition 1");
+        debugger.process_input("ignore 1 2");
+        run_to_breakpoint(&mut debugger);
+        assert_eq!(debugger.machine.get_cpu().get_reg(3), 6);
+        assert_eq!(debugger.breakpoints[0].hits, 4);
+
+        debugger.process_input("break 0x204");
+        debugger.process_input("disable 1");
+        assert_eq!(run_to_breakpoint(&mut debugger), 2);
+
+        debugger.process_input("delete 2");
+        debugger.process_input("enable 1");
+        assert_eq!(run_to_breakpoint(&mut debugger), 1);
+    }
+
+    #[test]
+    fn test_parse_watchpoints() {
+        use DebuggerCommand::Break;
+
+        assert!(matches!(Debugger::parse_input("watch V5"),
+                         Ok(Break(BreakpointKind::Write(Watch::Register(5)), None))));
+        assert!(matches!(Debugger::parse_input("watch [0x3F0]"),
+                         Ok(Break(BreakpointKind::Write(Watch::Memory(0x3F0, 0x3F0)), None))));
+        assert!(matches!(Debugger::parse_input("rwatch [0x3F0..0x3FF] if V0 > 1"),
+                         Ok(Break(BreakpointKind::Read(Watch::Memory(0x3F0, 0x3FF)), Some(_)))));
+        assert!(Debugger::parse_input("rwatch V5").is_err());
+        assert!(Debugger::parse_input("watch [0x3FF..0x3F0]").is_err());
+        assert!(Debugger::parse_input("watch 0x3F0").is_err());
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // LD V5, 0x07; LD I, 0x3F0; LD [I], V0..V5; LD V0..V1, [I]; DRW V0, V0, 1
+        let mut debugger = debugger_with(&[
+            0x65, 0x07, 0xA3, 0xF0, 0xF6, 0x55, 0xF1, 0x65, 0xD0, 0x01, 0x12, 0x0A,
+        ]);
+
+        debugger.process_input("watch V5");
+        debugger.process_input("watch [0x3F5]");
+        debugger.process_input("rwatch [0x3F0..0x3F0]");
+
+        assert_eq!(debugger.step(), Some((1, Some("V5: 0x00 => 0x07".to_owned()))));
+        assert_eq!(debugger.step(), None);
+        assert_eq!(debugger.step(), Some((2, Some("[0x3F5]: 0x00 => 0x07".to_owned()))));
+        assert_eq!(debugger.step(), Some((3, Some("[0x3F0] read: 0x00".to_owned()))));
+
+        debugger.process_input("delete 3");
+        assert_eq!(debugger.step(), None);
+    }
+
+    // Runs the command like the front-ends do until execution stops,
+    // returning the number of instructions executed.
+    fn run_to_target(debugger: &mut Debugger, input: &str) -> usize {
+        debugger.process_input(input);
+        let mut count = 0;
+        while debugger.is_running() {
+            debugger.advance();
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut debugger = debugger_with(&NESTED_CALLS);
+
+        assert!(matches!(Debugger::parse_input("until 0x20A"), Ok(DebuggerCommand::Until(0x20A))));
+        assert!(Debugger::parse_input("until").is_err());
+
+        debugger.process_input("step");
+        assert_eq!(debugger.current_pc, 0x206);
+
+        // Steps over the nested call in a single command.
+        assert_eq!(run_to_target(&mut debugger, "next"), 3);
+        assert_eq!(debugger.current_pc, 0x208);
+        assert_eq!(debugger.machine.get_cpu().get_sp(), 1);
+
+        assert_eq!(run_to_target(&mut debugger, "finish"), 1);
+        assert_eq!(debugger.current_pc, 0x202);
+        assert_eq!(debugger.machine.get_cpu().get_sp(), 0);
+
+        // Nothing left to finish in the outermost frame.
+        debugger.process_input("finish");
+        assert!(debugger.need_input);
+
+        debugger.process_input("run");
+        debugger.need_input = true;
+        assert_eq!(run_to_target(&mut debugger, "until 0x20C"), 3);
+        assert_eq!(debugger.current_pc, 0x20C);
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut debugger = debugger_with(&NESTED_CALLS);
+        debugger.set_symbols(SymbolTable::parse("200 main\n206 update\n20A draw").unwrap());
+
+        assert!(matches!(Debugger::parse_input("bt"), Ok(DebuggerCommand::Backtrace)));
+        assert!(matches!(Debugger::parse_input("frame 2"), Ok(DebuggerCommand::Frame(2))));
+        assert!(matches!(Debugger::parse_input("frame"), Ok(DebuggerCommand::Frame(0))));
+
+        run_to_target(&mut debugger, "until 0x20C");
+        assert_eq!(debugger.frames(), vec![0x20C, 0x206, 0x200]);
+        assert_eq!(debugger.describe_frame(0, 0x20C), "#0  0x20C in draw+0x2");
+        assert_eq!(debugger.describe_frame(1, 0x206), "#1  0x206 in update, returns to 0x208");
+    }
+
+    #[test]
+    fn test_context_at_end_of_memory() {
+        let debugger = debugger();
+
+        let addresses: Vec<Address> = debugger.get_execution_context(0xFFFE).iter()
+                                              .map(|(address, _)| *address).collect();
+        assert_eq!(addresses, vec![0xFFFA, 0xFFFC, 0xFFFE]);
+    }
+
+    #[test]
+    fn test_examine_and_patch() {
+        let mut debugger = debugger();
+
+        assert!(matches!(Debugger::parse_input("x/16xb 0x300"),
+                         Ok(DebuggerCommand::Examine(16, ExamineFormat::Hex, _))));
+        assert!(matches!(Debugger::parse_input("x/s I"),
+                         Ok(DebuggerCommand::Examine(1, ExamineFormat::Sprite, _))));
+        assert!(Debugger::parse_input("x/4q 0x300").is_err());
+        assert!(Debugger::parse_input("poke 0x300 0x100").is_err());
+        assert!(Debugger::parse_input("set V3 =").is_err());
+
+        debugger.process_input("poke 0x300 0xFF 0x81 3");
+        debugger.process_input("set I = 0x300");
+        debugger.process_input("set V3 = [I + 1] & 0x0F");
+        debugger.process_input("set DT = 60");
+        debugger.process_input("set PC=0x202");
+        debugger.process_input("set context_span 3");
+
+        let cpu = debugger.machine.get_cpu();
+        assert_eq!(cpu.get_i(), 0x300);
+        assert_eq!(cpu.get_reg(3), 0x01);
+        assert_eq!(cpu.get_delay_timer(), 60);
+        assert_eq!(debugger.current_pc, 0x202);
+        assert_eq!(debugger.variables["context_span"], 3);
+
+        let examine = |text| match Debugger::parse_input(text) {
+            Ok(DebuggerCommand::Examine(count, format, address)) => {
+                debugger.examine(count, format, &address).unwrap()
+            },
+            _ => panic!("not an examine command"),
+        };
+        assert_eq!(examine("x/3xb I"), "0x300: 0xFF 0x81 0x03\n");
+        assert_eq!(examine("x/2d 0x300"), "0x300: 255 129\n");
+        assert_eq!(examine("x/2t 0x300"), "0x300: 11111111 10000001\n");
+        assert_eq!(examine("x/2s 0x300"), "0x300: ▉▉▉▉▉▉▉▉\n0x301: ▉......▉\n");
+        assert_eq!(examine("x/4x 0xFFE"), "0xFFE: 0x00 0x00\n");
+        assert_eq!(examine("x/18446744073709551615x 0xFFF"), "0xFFF: 0x00\n");
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut debugger = debugger();
+        debugger.set_symbols(SymbolTable::parse("200 main\n204 loop\n300 data").unwrap());
+
+        assert!(matches!(Debugger::parse_input("disas main"),
+                         Ok(DebuggerCommand::Disassemble(Some(_), None))));
+        assert!(matches!(Debugger::parse_input("disas 0x300 4"),
+                         Ok(DebuggerCommand::Disassemble(Some(_), Some(4)))));
+        assert!(Debugger::parse_input("disas 0x300 four").is_err());
+
+        assert_eq!(debugger.disassembly_range(Some("main"), None), Ok((0x200, 2)));
+        assert_eq!(debugger.disassembly_range(Some("loop"), Some(1)), Ok((0x204, 1)));
+        assert_eq!(debugger.disassembly_range(Some("data"), None), Ok((0x300, DISAS_COUNT)));
+        assert_eq!(debugger.disassembly_range(Some("0x202"), None), Ok((0x202, DISAS_COUNT)));
+        assert_eq!(debugger.disassembly_range(None, Some(3)), Ok((0x200, 3)));
+        assert!(debugger.disassembly_range(Some("nowhere"), None).is_err());
+
+        // Patched code is listed as it is in memory.
+        debugger.process_input("poke 0x202 0x74 0x02");
+        assert_eq!(debugger.disassemble(0x200, 2), vec![
+            (0x200, InstructionData::Ld(3, 0x00)),
+            (0x202, InstructionData::Add(4, 0x02)),
+        ]);
+        assert_eq!(debugger.disassemble(0xFFC, 4).len(), 2);
+    }
+
+    #[test]
+    fn test_source_lines() {
+        // 0x200: LD V0, 1; LD V1, 2; CALL 0x20A; JP 0x206; 0x20A: LD V2, 3; RET
+        let mut debugger = debugger_with(&[
+            0x60, 0x01, 0x61, 0x02, 0x22, 0x0A, 0x12, 0x06, 0x00, 0x00, 0x62, 0x03, 0x00, 0xEE,
+        ]);
+        let table = "200 1 game.s\n202 1 game.s\n204 2 game.s\n206 3 game.s\n20A 6 game.s\n20C 7 game.s";
+        debugger.set_line_table(LineTable::parse(table).unwrap());
+
+        assert!(matches!(Debugger::parse_input("break game.s:6"), Ok(DebuggerCommand::BreakLine(_, 6, None))));
+        assert!(matches!(Debugger::parse_input("b src/game.s:6 if V0 == 1"),
+                         Ok(DebuggerCommand::BreakLine(_, 6, Some(_)))));
+        assert!(Debugger::parse_input("break game.s:six").is_err());
+        assert!(Debugger::parse_input("break game.s:6 when V0").is_err());
+
+        debugger.process_input("break /home/me/game.s:5");
+        assert_eq!(debugger.take_output(), vec!["Breakpoint 1 at 0x20A: /home/me/game.s:6."]);
+        debugger.process_input("break game.s:8");
+        assert_eq!(debugger.take_output(), vec!["No code at game.s:8."]);
+
+        // Both instructions of the first line run in a single step.
+        assert_eq!(run_to_target(&mut debugger, "step"), 2);
+        assert_eq!(debugger.current_pc, 0x204);
+
+        // The called routine's lines are skipped by `next`.
+        debugger.process_input("disable 1");
+        assert_eq!(run_to_target(&mut debugger, "next"), 3);
+        assert_eq!(debugger.current_pc, 0x206);
+
+        debugger.process_input("stepi");
+        assert_eq!(debugger.current_pc, 0x206);
+
+        debugger.sources.insert("game.s".to_owned(), vec![
+            "  LD V0, 1".to_owned(), "  CALL sub".to_owned(), "loop: JP loop".to_owned(),
+        ]);
+        debugger.process_input("break game.s:2");
+        debugger.take_output();
+        debugger.show_context_at(0x206);
+        let output: Vec<String> = debugger.take_output().iter().map(|line| strip_escapes(line)).collect();
+        assert_eq!(output, vec![
+            "       1    LD V0, 1",
+            "*      2    CALL sub",
+            "  ->   3  loop: JP loop",
+        ]);
+
+        // Lines missing from the loaded source fall back to instructions.
+        debugger.show_context_at(0x20A);
+        assert!(debugger.take_output().iter().any(|line| line.contains("0x20A")));
+    }
+}
@@ -0,0 +1,494 @@
+extern crate termion;
+
+use std::io;
+use std::io::{Stdout, Write};
+use std::thread;
+use std::time::Duration;
+
+use termion::{async_stdin, clear, color, cursor, style, terminal_size};
+use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::AlternateScreen;
+use termion::AsyncReader;
+
+use crate::debugger::Debugger;
+use crate::machine::Machine;
+use crate::memory::Memory;
+use crate::specs::{Address, Byte, REGISTERS_COUNT};
+use crate::tui::Terminal;
+
+static PROMPT: &str = "(chip8-debug) ";
+
+// Instructions executed between two redraws while the program runs.
+const INSTRUCTIONS_PER_REDRAW: usize = 1000;
+const LOG_LINES: usize = 6;
+const LOG_HISTORY: usize = 500;
+const CODE_WIDTH: usize = 32;
+const REGISTERS_WIDTH: usize = 22;
+const REGISTERS_HEIGHT: usize = 12;
+const MIN_SIZE: (usize, usize) = (100, 24);
+
+// A line of text made of plain and highlighted spans.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Line {
+    spans: Vec<(String, bool)>,
+}
+
+impl Line {
+    fn plain<S: Into<String>>(text: S) -> Self {
+        Line { spans: vec![(text.into(), false)] }
+    }
+
+    fn push<S: Into<String>>(&mut self, text: S, highlight: bool) {
+        self.spans.push((text.into(), highlight));
+    }
+
+    // Truncates or pads the line to exactly `width` columns.
+    fn render(&self, width: usize) -> String {
+        let mut output = String::new();
+        let mut remaining = width;
+
+        for (text, highlight) in &self.spans {
+            let text: String = text.chars().take(remaining).collect();
+            remaining -= text.chars().count();
+
+            if text.is_empty() {
+                continue;
+            } else if *highlight {
+                output += &format!("{}{}{}{}", style::Bold, color::Fg(color::Green), text, style::Reset);
+            } else {
+                output += &text;
+            }
+        }
+
+        output + &" ".repeat(remaining)
+    }
+}
+
+// Removes the colour codes the line-oriented debugger puts in its output.
+pub(crate) fn strip_escapes(text: &str) -> String {
+    let mut output = String::new();
+    let mut chars = text.chars();
+
+    while let Some(chr) = chars.next() {
+        if chr == '\x1B' {
+            for chr in chars.by_ref() {
+                if chr.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            output.push(chr);
+        }
+    }
+
+    output
+}
+
+// Values shown in the register pane, compared with the ones from the last
+// stop to highlight what changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Registers {
+    v: [Byte; REGISTERS_COUNT],
+    i: Address,
+    pc: Address,
+    sp: Byte,
+    dt: Byte,
+    st: Byte,
+}
+
+impl Registers {
+    fn capture(machine: &Machine) -> Self {
+        let cpu = machine.get_cpu();
+        let mut v = [0; REGISTERS_COUNT];
+        for (index, value) in v.iter_mut().enumerate() {
+            *value = cpu.get_reg(index as u8);
+        }
+
+        Registers {
+            v,
+            i: cpu.get_i(),
+            pc: cpu.get_pc(),
+            sp: cpu.get_sp(),
+            dt: cpu.get_delay_timer(),
+            st: cpu.get_sound_timer(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Layout {
+    code: Rect,
+    registers: Rect,
+    stack: Rect,
+    screen: Rect,
+    memory: Rect,
+    log: Rect,
+    command: usize,
+}
+
+fn layout(columns: usize, rows: usize, screen_rows: usize) -> Layout {
+    let log_height = LOG_LINES + 1;
+    let top_height = rows - 1 - log_height;
+    let side_x = CODE_WIDTH + 1;
+    let right_x = side_x + REGISTERS_WIDTH + 1;
+    let right_width = columns - right_x;
+    // The memory pane keeps a few rows when the screen is in hires mode.
+    let screen_height = (screen_rows + 1).min(top_height.saturating_sub(6));
+
+    Layout {
+        code: Rect { x: 0, y: 0, width: CODE_WIDTH, height: top_height },
+        registers: Rect { x: side_x, y: 0, width: REGISTERS_WIDTH, height: REGISTERS_HEIGHT },
+        stack: Rect {
+            x: side_x,
+            y: REGISTERS_HEIGHT,
+            width: REGISTERS_WIDTH,
+            height: top_height - REGISTERS_HEIGHT,
+        },
+        screen: Rect { x: right_x, y: 0, width: right_width, height: screen_height },
+        memory: Rect {
+            x: right_x,
+            y: screen_height,
+            width: right_width,
+            height: top_height - screen_height,
+        },
+        log: Rect { x: 0, y: top_height, width: columns, height: log_height },
+        command: rows - 1,
+    }
+}
+
+fn code_pane(debugger: &Debugger, rows: usize) -> Vec<Line> {
+    let pc = debugger.get_current_pc();
+    let start = pc.saturating_sub((rows / 3) as Address * 2);
+    let mut lines = Vec::new();
+
+    for (address, instr) in debugger.disassemble(start, rows) {
+        if let Some((name, 0)) = debugger.get_symbols().lookup(address) {
+            lines.push(Line::plain(format!("{}:", name)));
+        }
+
+        let text = format!("{:<2}{:#05X}  {}", debugger.breakpoint_marker(address), address, instr);
+        let mut line = Line::default();
+        line.push(text, address == pc);
+        lines.push(line);
+    }
+
+    // Keeps the PC visible when labels pushed it down.
+    let pc_row = lines.iter().position(|line| line.spans[0].1).unwrap_or(0);
+    let skip = (pc_row + 1).saturating_sub(rows);
+    lines.into_iter().skip(skip).take(rows).collect()
+}
+
+fn registers_pane(current: &Registers, previous: &Registers) -> Vec<Line> {
+    let mut lines = Vec::new();
+
+    for pair in 0..REGISTERS_COUNT / 2 {
+        let mut line = Line::default();
+        for index in [pair * 2, pair * 2 + 1] {
+            line.push(format!("V{:X} ", index), false);
+            line.push(format!("{:#04X}", current.v[index]), current.v[index] != previous.v[index]);
+            line.push("    ", false);
+        }
+        lines.push(line);
+    }
+
+    let mut line = Line::plain("I  ");
+    line.push(format!("{:#05X}", current.i), current.i != previous.i);
+    line.push("   PC ", false);
+    line.push(format!("{:#05X}", current.pc), current.pc != previous.pc);
+    lines.push(line);
+
+    let mut line = Line::plain("SP ");
+    line.push(format!("{:#04X}", current.sp), current.sp != previous.sp);
+    lines.push(line);
+
+    let mut line = Line::plain("DT ");
+    line.push(format!("{:#04X}", current.dt), current.dt != previous.dt);
+    line.push("    ST ", false);
+    line.push(format!("{:#04X}", current.st), current.st != previous.st);
+    lines.push(line);
+
+    lines
+}
+
+fn stack_pane(debugger: &Debugger) -> Vec<Line> {
+    let stack = debugger.get_machine().get_cpu().get_stack();
+    if stack.is_empty() {
+        return vec![Line::plain("(empty)")];
+    }
+
+    stack.iter().enumerate().rev().map(|(level, &ret)| {
+        let mut text = format!("{:X} {:#05X}", level, ret);
+        if let Some(name) = debugger.get_symbols().describe(ret) {
+            text += &format!(" {}", name);
+        }
+        Line::plain(text)
+    }).collect()
+}
+
+// Shows memory around I, where sprites and saved registers usually are.
+fn memory_pane(machine: &Machine, width: usize, rows: usize) -> Vec<Line> {
+    let ram = machine.get_bus().get_ram();
+    let i = machine.get_cpu().get_i() as usize;
+    let per_row = if width >= 7 + 16 * 3 { 16 } else { 8 };
+    let start = (i / per_row).saturating_sub(1) * per_row;
+
+    (0..rows).map(|row| start + row * per_row)
+             .take_while(|&address| address < ram.size())
+             .map(|address| {
+                 let mut line = Line::plain(format!("{:#05X}:", address));
+                 for address in address..(address + per_row).min(ram.size()) {
+                     line.push(" ", false);
+                     line.push(format!("{:02X}", ram.read(address as Address)), address == i);
+                 }
+                 line
+             })
+             .collect()
+}
+
+// Screens larger than the pane are shrunk by a whole factor.
+fn screen_pane(machine: &Machine, columns: usize, rows: usize) -> Vec<Line> {
+    let frame_buffer = machine.get_bus().get_frame_buffer();
+    let scale = frame_buffer.width().div_ceil(columns.max(1))
+                            .max(frame_buffer.height().div_ceil(rows.max(1) * 2));
+    let height = frame_buffer.height().div_ceil(scale);
+
+    (0..height.div_ceil(2))
+        .map(|row| Line::plain(Terminal::render_row(frame_buffer, row, scale)))
+        .collect()
+}
+
+pub struct DebuggerTui {
+    screen: AlternateScreen<RawTerminal<Stdout>>,
+    input: Keys<AsyncReader>,
+    command: String,
+    history: Vec<String>,
+    history_index: usize,
+    log: Vec<String>,
+    previous: Option<Registers>,
+    size: (usize, usize),
+    last_frame: String,
+}
+
+impl DebuggerTui {
+    pub fn new() -> io::Result<Self> {
+        let screen = AlternateScreen::from(io::stdout().into_raw_mode()?);
+
+        Ok(DebuggerTui {
+            screen,
+            input: async_stdin().keys(),
+            command: String::new(),
+            history: Vec::new(),
+            history_index: 0,
+            log: Vec::new(),
+            previous: None,
+            size: (0, 0),
+            last_frame: String::new(),
+        })
+    }
+
+    fn submit(&mut self, debugger: &mut Debugger) {
+        let command = self.command.split_off(0);
+
+        self.log.push(format!("{}{}", PROMPT, command));
+        if !command.trim().is_empty() {
+            self.history.push(command.clone());
+        }
+        self.history_index = self.history.len();
+
+        self.previous = Some(Registers::capture(debugger.get_machine()));
+        debugger.process_input(&command);
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if older && self.history_index > 0 {
+            self.history_index -= 1;
+        } else if !older && self.history_index < self.history.len() {
+            self.history_index += 1;
+        }
+
+        self.command = self.history.get(self.history_index).cloned().unwrap_or_default();
+    }
+
+    fn handle_keys(&mut self, debugger: &mut Debugger) {
+        while let Some(Ok(key)) = self.input.next() {
+            match key {
+                Key::Char('\n') => self.submit(debugger),
+                Key::Char(chr) => self.command.push(chr),
+                Key::Backspace => {
+                    self.command.pop();
+                },
+                Key::Up => self.browse_history(true),
+                Key::Down => self.browse_history(false),
+                Key::Ctrl('c') | Key::Esc => {
+                    if debugger.is_running() {
+                        debugger.interrupt();
+                    } else {
+                        self.command.clear();
+                    }
+                },
+                Key::Ctrl('d') if self.command.is_empty() => debugger.process_input("quit"),
+                _ => {},
+            }
+        }
+    }
+
+    fn draw_pane(output: &mut String, rect: Rect, title: &str, lines: &[Line]) {
+        if rect.height == 0 || rect.width == 0 {
+            return;
+        }
+
+        let mut header = Line::default();
+        header.push(format!("─ {} ", title), true);
+        header.push("─".repeat(rect.width), false);
+        *output += &format!("{}{}", cursor::Goto(rect.x as u16 + 1, rect.y as u16 + 1), header.render(rect.width));
+
+        for row in 0..rect.height - 1 {
+            let line = lines.get(row).cloned().unwrap_or_default();
+            *output += &format!("{}{}", cursor::Goto(rect.x as u16 + 1, (rect.y + row) as u16 + 2),
+                                line.render(rect.width));
+        }
+    }
+
+    fn draw(&mut self, debugger: &Debugger) -> io::Result<()> {
+        let (columns, rows) = terminal_size()?;
+        let size = (columns as usize, rows as usize);
+        let mut output = String::new();
+
+        if size != self.size {
+            output += &format!("{}", clear::All);
+            self.size = size;
+            self.last_frame.clear();
+        }
+
+        if size.0 < MIN_SIZE.0 || size.1 < MIN_SIZE.1 {
+            output += &format!("{}Terminal too small, {}x{} needed.{}", cursor::Goto(1, 1),
+                               MIN_SIZE.0, MIN_SIZE.1, clear::UntilNewline);
+            write!(self.screen, "{}", output)?;
+            return self.screen.flush();
+        }
+
+        let machine = debugger.get_machine();
+        let screen_rows = machine.get_bus().get_frame_buffer().height().div_ceil(2);
+        let layout = layout(size.0, size.1, screen_rows);
+        let screen = screen_pane(machine, layout.screen.width, layout.screen.height - 1);
+        let current = Registers::capture(machine);
+        let previous = self.previous.unwrap_or(current);
+
+        DebuggerTui::draw_pane(&mut output, layout.code, "Code",
+                               &code_pane(debugger, layout.code.height - 1));
+        DebuggerTui::draw_pane(&mut output, layout.registers, "Registers",
+                               &registers_pane(&current, &previous));
+        DebuggerTui::draw_pane(&mut output, layout.stack, "Stack", &stack_pane(debugger));
+        DebuggerTui::draw_pane(&mut output, layout.screen, "Screen", &screen);
+        DebuggerTui::draw_pane(&mut output, layout.memory, "Memory",
+                               &memory_pane(machine, layout.memory.width, layout.memory.height - 1));
+
+        let log: Vec<Line> = self.log.iter().skip(self.log.len().saturating_sub(LOG_LINES))
+                                     .map(|line| Line::plain(line.as_str()))
+                                     .collect();
+        let title = if debugger.is_running() { "Running (Ctrl-C to stop)" } else { "Log" };
+        DebuggerTui::draw_pane(&mut output, layout.log, title, &log);
+
+        let command = Line::plain(format!("{}{}", PROMPT, self.command));
+        output += &format!("{}{}{}", cursor::Goto(1, layout.command as u16 + 1),
+                           command.render(size.0 - 1), cursor::Show);
+        let column = (PROMPT.len() + self.command.chars().count()).min(size.0 - 1);
+        output += &format!("{}", cursor::Goto(column as u16 + 1, layout.command as u16 + 1));
+
+        // Nothing is written while the debugger waits for input.
+        if output != self.last_frame {
+            write!(self.screen, "{}", output)?;
+            self.last_frame = output;
+        }
+        self.screen.flush()
+    }
+
+    pub fn run(&mut self, debugger: &mut Debugger) -> io::Result<()> {
+        debugger.set_context_output(false);
+
+        while !debugger.must_exit() {
+            self.handle_keys(debugger);
+
+            for _ in 0..INSTRUCTIONS_PER_REDRAW {
+                if !debugger.is_running() {
+                    break;
+                }
+                debugger.advance();
+            }
+
+            for line in debugger.take_output() {
+                self.log.push(strip_escapes(&line));
+            }
+            let excess = self.log.len().saturating_sub(LOG_HISTORY);
+            self.log.drain(..excess);
+
+            self.draw(debugger)?;
+
+            if !debugger.is_running() {
+                thread::sleep(Duration::from_millis(15));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for DebuggerTui {
+    fn drop(&mut self) {
+        let _ = write!(self.screen, "{}", cursor::Show);
+        let _ = self.screen.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let mut line = Line::plain("V0 ");
+        line.push("0x05", true);
+
+        assert_eq!(line.render(3), "V0 ");
+        assert_eq!(strip_escapes(&line.render(9)), "V0 0x05  ");
+        assert_eq!(strip_escapes("\x1B[32m-> 0x200\x1B[m"), "-> 0x200");
+    }
+
+    #[test]
+    fn test_registers_pane() {
+        let previous = Registers { v: [0; REGISTERS_COUNT], i: 0x300, pc: 0x200, sp: 0, dt: 0, st: 0 };
+        let mut current = previous;
+        current.v[3] = 0x10;
+        current.pc = 0x202;
+
+        let lines = registers_pane(&current, &previous);
+        let highlighted: Vec<&str> = lines.iter()
+                                          .flat_map(|line| line.spans.iter())
+                                          .filter(|(_, highlight)| *highlight)
+                                          .map(|(text, _)| text.as_str())
+                                          .collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(highlighted, vec!["0x10", "0x202"]);
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = layout(120, 40, 16);
+
+        assert_eq!(layout.command, 39);
+        assert_eq!(layout.log, Rect { x: 0, y: 32, width: 120, height: 7 });
+        assert_eq!(layout.screen, Rect { x: 56, y: 0, width: 64, height: 17 });
+        assert_eq!(layout.memory.y + layout.memory.height, 32);
+        assert_eq!(layout.stack.y + layout.stack.height, 32);
+    }
+}
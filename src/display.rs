@@ -1,52 +1,205 @@
 use std::fmt;
+use std::str::FromStr;
 use crate::memory::Memory;
-use crate::specs::Byte;
+use crate::specs::{Byte, PLANES_COUNT};
 
+const FRAME_WIDTH: usize = 64;
 const FRAME_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpriteEdge {
+    Clip,
+    Wrap,
+}
 
 pub struct FrameBuffer {
-    buffer: [u64; FRAME_HEIGHT],
-    erased: bool,
+    planes: [[u128; HIRES_HEIGHT]; PLANES_COUNT],
+    selected: u8,
+    hires: bool,
+    edge: SpriteEdge,
 }
 
 impl FrameBuffer {
     pub fn new() -> Self {
         FrameBuffer {
-            buffer: [0x0; FRAME_HEIGHT],
-            erased: false
+            planes: [[0x0; HIRES_HEIGHT]; PLANES_COUNT],
+            selected: 0b01,
+            hires: false,
+            edge: SpriteEdge::Clip,
         }
     }
 
-    fn write_byte(&mut self, x: usize, y: usize, byte: Byte) {
-        let mut x_iter = x;
-        let mut mask = 0b10000000;
+    fn toggle(&mut self, plane: usize, addr: (usize, usize), value: bool) {
+        let (x, y) = addr;
+        self.planes[plane][y] ^= (value as u128) << (self.width() - 1 - x);
+    }
+
+    fn edge_coordinate(&self, coordinate: usize, size: usize) -> Option<usize> {
+        if coordinate < size {
+            Some(coordinate)
+        } else if self.edge == SpriteEdge::Wrap {
+            Some(coordinate % size)
+        } else {
+            None
+        }
+    }
 
-        for i in 0..8 {
-            let bit = (byte & mask) >> (7 - i);
-            self.write((x_iter, y), bit != 0);
-            mask >>= 1;
-            x_iter += 1;
-            if x_iter == 64 {
-                x_iter = 0;
-            }
+    fn row_mask(&self) -> u128 {
+        if self.hires {
+            !0x0
+        } else {
+            (1 << FRAME_WIDTH) - 1
         }
     }
 
+    // Iterates over the bits of the selection without borrowing the buffer.
+    fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let selected = self.selected;
+        (0..PLANES_COUNT).filter(move |plane| selected & (1 << plane) != 0)
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            FRAME_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            FRAME_HEIGHT
+        }
+    }
+
+    pub fn pixel(&self, addr: (usize, usize)) -> usize {
+        let (x, y) = addr;
+        let shift = self.width() - 1 - x;
+
+        (0..PLANES_COUNT).fold(0, |color, plane| {
+            color | ((((self.planes[plane][y] >> shift) & 0b1) as usize) << plane)
+        })
+    }
+
+    pub fn set_sprite_edge(&mut self, edge: SpriteEdge) {
+        self.edge = edge;
+    }
+
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [[0x0; HIRES_HEIGHT]; PLANES_COUNT];
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & ((1 << PLANES_COUNT) - 1) as u8;
+    }
+
+    pub fn selected_planes_count(&self) -> usize {
+        self.selected.count_ones() as usize
+    }
+
     pub fn clear_screen(&mut self) {
-        for i in 0..FRAME_HEIGHT {
-            self.buffer[i] = 0x0;
+        for plane in self.selected_planes() {
+            self.planes[plane] = [0x0; HIRES_HEIGHT];
         }
     }
 
-    pub fn write_bytes(&mut self, pos: <Self as Memory>::Address, bytes: &[Byte]) -> bool {
-        let (x, mut y) = pos;
+    pub fn scroll_down(&mut self, rows: usize) {
+        for plane in self.selected_planes() {
+            let buffer = &mut self.planes[plane];
+            for y in (0..HIRES_HEIGHT).rev() {
+                buffer[y] = if y >= rows {
+                    buffer[y - rows]
+                } else {
+                    0x0
+                };
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        let height = self.height();
+
+        for plane in self.selected_planes() {
+            let buffer = &mut self.planes[plane];
+            for y in 0..height {
+                buffer[y] = if y + rows < height {
+                    buffer[y + rows]
+                } else {
+                    0x0
+                };
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        for plane in self.selected_planes() {
+            for row in self.planes[plane].iter_mut() {
+                *row >>= columns;
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        let mask = self.row_mask();
+
+        for plane in self.selected_planes() {
+            for row in self.planes[plane].iter_mut() {
+                *row = (*row << columns) & mask;
+            }
+        }
+    }
+
+    // Places a sprite row of the given width at column x, the edge policy
+    // decides what happens to the bits that overflow the screen.
+    fn sprite_row(&self, bits: u128, size: usize, x: usize) -> u128 {
+        let width = self.width();
+
+        if x + size <= width {
+            return bits << (width - x - size);
+        }
+
+        let clipped = bits >> (x + size - width);
+        match self.edge {
+            SpriteEdge::Clip => clipped,
+            SpriteEdge::Wrap => clipped | ((bits << (2 * width - x - size)) & self.row_mask()),
+        }
+    }
+
+    fn write_sprite(&mut self, pos: <Self as Memory>::Address, bytes: &[Byte], row_size: usize) -> bool {
+        let (x, y) = (pos.0 % self.width(), pos.1 % self.height());
+        let size = bytes.len() / self.selected_planes_count().max(1);
+        let mut collision = false;
+
+        for (idx, plane) in self.selected_planes().enumerate() {
+            for (row, data) in bytes[idx * size..(idx + 1) * size].chunks(row_size).enumerate() {
+                let y = match self.edge_coordinate(y + row, self.height()) {
+                    Some(y) => y,
+                    None => break,
+                };
+
+                let bits = data.iter().fold(0, |bits, byte| (bits << 8) | *byte as u128);
+                let sprite = self.sprite_row(bits, data.len() * 8, x);
+                let line = &mut self.planes[plane][y];
 
-        for byte in bytes {
-            self.write_byte(x, y, *byte);
-            y += 1;
+                collision |= *line & sprite != 0;
+                *line ^= sprite;
+            }
         }
 
-        self.erased
+        collision
+    }
+
+    pub fn write_bytes(&mut self, pos: <Self as Memory>::Address, bytes: &[Byte]) -> bool {
+        self.write_sprite(pos, bytes, 1)
+    }
+
+    pub fn write_wide_bytes(&mut self, pos: <Self as Memory>::Address, bytes: &[Byte]) -> bool {
+        self.write_sprite(pos, bytes, 2)
     }
 }
 
@@ -55,29 +208,47 @@ impl Memory for FrameBuffer {
     type Value = bool;
 
     fn read(&self, addr: Self::Address) -> Self::Value {
-        let (x, y) = addr;
-        (self.buffer[y] & (1 << (63 - x))) >> (63 - x) != 0
+        self.pixel(addr) != 0
     }
 
     fn write(&mut self, addr: Self::Address, value: Self::Value) {
-        self.erased = false;
+        let addr = (addr.0 % self.width(), addr.1 % self.height());
 
-        let (x, y) = addr;
-        let tmp = self.buffer[y];
-        self.buffer[y] ^= (value as u64) << (63 - x);
+        for plane in self.selected_planes() {
+            self.toggle(plane, addr, value);
+        }
+    }
+}
+
+impl FromStr for SpriteEdge {
+    type Err = String;
 
-        if tmp & self.buffer[y] > 0 {
-            self.erased = true;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clip" => Ok(SpriteEdge::Clip),
+            "wrap" => Ok(SpriteEdge::Wrap),
+            _ => Err(format!("Unknown sprite edge policy: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for SpriteEdge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpriteEdge::Clip => write!(f, "clip"),
+            SpriteEdge::Wrap => write!(f, "wrap"),
         }
     }
 }
 
 impl fmt::Display for FrameBuffer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:X>67}", "\n")?;
-        for row in 0..32 {
+        let border = "X".repeat(self.width() + 2);
+
+        writeln!(f, "{}", border)?;
+        for row in 0..self.height() {
             write!(f, "X")?;
-            for col in 0..64 {
+            for col in 0..self.width() {
                 let chr = if self.read((col, row)) {
                     "▉"
                 } else {
@@ -85,9 +256,9 @@ impl fmt::Display for FrameBuffer {
                 };
                 write!(f, "{}", chr)?;
             }
-            write!(f, "X\n")?;
+            writeln!(f, "X")?;
         }
-        write!(f, "{:X>67}", "\n")?;
+        writeln!(f, "{}", border)?;
 
         Ok(())
     }
@@ -100,8 +271,108 @@ mod tests {
     #[test]
     fn test_read() {
         let mut buff = FrameBuffer::new();
-        buff.buffer[3] |= 0xF0F0F0F0F0F0F0F0;
-        assert_eq!(buff.read((3, 3)), true);
-        assert_eq!(buff.read((3, 9)), false);
+        buff.planes[0][3] |= 0xF0F0F0F0F0F0F0F0;
+        assert!(buff.read((3, 3)));
+        assert!(!buff.read((3, 9)));
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut buff = FrameBuffer::new();
+        buff.write((10, 10), true);
+        buff.set_hires(true);
+
+        assert_eq!((buff.width(), buff.height()), (128, 64));
+        assert!(!buff.read((10, 10)));
+
+        buff.write((127, 63), true);
+        assert!(buff.read((127, 63)));
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut buff = FrameBuffer::new();
+        buff.select_planes(0b11);
+        assert_eq!(buff.selected_planes_count(), 2);
+
+        buff.write_bytes((0, 0), &[0b11000000, 0b10100000]);
+        assert_eq!(buff.pixel((0, 0)), 3);
+        assert_eq!(buff.pixel((1, 0)), 1);
+        assert_eq!(buff.pixel((2, 0)), 2);
+
+        buff.select_planes(0b10);
+        buff.clear_screen();
+        assert_eq!(buff.pixel((0, 0)), 1);
+        assert_eq!(buff.pixel((2, 0)), 0);
+    }
+
+    #[test]
+    fn test_sprite_edges() {
+        let mut buff = FrameBuffer::new();
+        let sprite = [0xFF; 5];
+
+        assert!(!buff.write_bytes((60, 30), &sprite));
+        assert!(buff.read((63, 31)));
+        assert!(!buff.read((0, 31)));
+        assert!(!buff.read((60, 0)));
+
+        // Start coordinates wrap whatever the policy.
+        assert!(buff.write_bytes((124, 94), &sprite));
+        assert!(!buff.read((63, 31)));
+
+        buff.clear_screen();
+        buff.set_sprite_edge(SpriteEdge::Wrap);
+        buff.write_bytes((60, 30), &sprite);
+        assert!(buff.read((3, 31)));
+        assert!(buff.read((60, 2)));
+        assert!(buff.read((3, 2)));
+        assert!(!buff.read((4, 3)));
+    }
+
+    #[test]
+    fn test_wide_sprite_rows() {
+        let mut buff = FrameBuffer::new();
+        buff.set_hires(true);
+        buff.set_sprite_edge(SpriteEdge::Wrap);
+
+        assert!(!buff.write_wide_bytes((120, 0), &[0x80, 0x01]));
+        assert!(buff.read((120, 0)));
+        assert!(buff.read((7, 0)));
+        assert!(!buff.read((127, 0)));
+
+        assert!(buff.write_wide_bytes((120, 0), &[0x00, 0x01]));
+        assert!(!buff.read((7, 0)));
+    }
+
+    #[test]
+    fn test_collision_over_whole_sprite() {
+        let mut buff = FrameBuffer::new();
+        buff.write((0, 0), true);
+
+        // Only the first pixel collides, later pixels must not reset the flag.
+        assert!(buff.write_bytes((0, 0), &[0xFF, 0xFF]));
+        assert!(!buff.read((0, 0)));
+        assert!(buff.read((7, 1)));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut buff = FrameBuffer::new();
+        buff.write((0, 0), true);
+        buff.write((63, 5), true);
+
+        buff.scroll_down(4);
+        assert!(buff.read((0, 4)));
+        assert!(!buff.read((0, 0)));
+
+        buff.scroll_right(4);
+        assert!(buff.read((4, 4)));
+        assert!(!buff.read((63, 9)));
+
+        buff.scroll_left(4);
+        assert!(buff.read((0, 4)));
+
+        buff.scroll_up(4);
+        assert!(buff.read((0, 0)));
     }
 }
@@ -0,0 +1,373 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::machine::Machine;
+use crate::memory::Memory;
+use crate::specs::Address;
+
+// Expressions used by the debugger, e.g. `V3 == 5 && [I + 1] > 0x30`.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    Register(u8),
+    I,
+    PC,
+    SP,
+    DT,
+    ST,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    BitAnd,
+    BitOr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Location(Location),
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+}
+
+static OPERATORS: [&str; 13] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "&", "|", "!",
+];
+
+pub fn parse_number(text: &str) -> Result<i64, String> {
+    let parsed = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else {
+        text.parse()
+    };
+
+    parsed.map_err(|_| format!("Invalid number: {}", text))
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<Chars> = text.chars().peekable();
+
+    while let Some(&chr) = chars.peek() {
+        if chr.is_whitespace() {
+            chars.next();
+        } else if chr.is_ascii_alphanumeric() || chr == '_' {
+            let mut word = String::new();
+            while let Some(&chr) = chars.peek() {
+                if !(chr.is_ascii_alphanumeric() || chr == '_') {
+                    break;
+                }
+                word.push(chr);
+                chars.next();
+            }
+
+            tokens.push(if chr.is_ascii_digit() {
+                Token::Number(parse_number(&word)?)
+            } else {
+                Token::Name(word)
+            });
+        } else {
+            chars.next();
+            tokens.push(match chr {
+                '(' => Token::Open,
+                ')' => Token::Close,
+                '[' => Token::OpenBracket,
+                ']' => Token::CloseBracket,
+                _ => {
+                    let pair: String = [chr].iter().chain(chars.peek()).collect();
+                    match OPERATORS.iter().find(|op| **op == pair) {
+                        Some(op) => {
+                            chars.next();
+                            Token::Op(op)
+                        },
+                        None => match OPERATORS.iter().find(|op| **op == chr.to_string()) {
+                            Some(op) => Token::Op(op),
+                            None => return Err(format!("Unexpected character: {}", chr)),
+                        },
+                    }
+                },
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+impl Location {
+    pub fn parse(name: &str) -> Option<Self> {
+        let upper = name.to_uppercase();
+
+        match upper.as_str() {
+            "I" => Some(Location::I),
+            "PC" => Some(Location::PC),
+            "SP" => Some(Location::SP),
+            "DT" => Some(Location::DT),
+            "ST" => Some(Location::ST),
+            _ => match upper.strip_prefix('V') {
+                Some(index) if index.len() == 1 => {
+                    u8::from_str_radix(index, 16).ok().map(Location::Register)
+                },
+                _ => None,
+            },
+        }
+    }
+
+    pub fn read(self, machine: &Machine) -> i64 {
+        let cpu = machine.get_cpu();
+
+        match self {
+            Location::Register(index) => cpu.get_reg(index) as i64,
+            Location::I => cpu.get_i() as i64,
+            Location::PC => cpu.get_pc() as i64,
+            Location::SP => cpu.get_sp() as i64,
+            Location::DT => cpu.get_delay_timer() as i64,
+            Location::ST => cpu.get_sound_timer() as i64,
+        }
+    }
+
+    // I and PC must stay within memory, PC leaving room for a whole
+    // instruction.
+    pub fn write(self, machine: &mut Machine, value: i64) -> Result<(), String> {
+        let memory_size = machine.get_bus().get_ram().size() as i64;
+        let limit = match self {
+            Location::I => memory_size - 1,
+            Location::PC => memory_size - 2,
+            Location::SP => return Err("SP cannot be assigned".to_owned()),
+            _ => 0xFF,
+        };
+        if value < 0 || value > limit {
+            return Err(format!("Value out of range: {:#X}", value));
+        }
+
+        let cpu = machine.get_cpu_mut();
+        match self {
+            Location::Register(index) => cpu.set_reg(index, value as u8),
+            Location::I => cpu.set_i(value as Address),
+            Location::PC => cpu.set_pc(value as Address),
+            Location::DT => cpu.set_delay_timer(value as u8),
+            Location::ST => cpu.set_sound_timer(value as u8),
+            Location::SP => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            Some(token) => Err(format!("Unexpected token: {:?}", token)),
+            None => Err("Unexpected end of expression".to_owned()),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        static LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne), ("<=", BinaryOp::Le),
+              (">=", BinaryOp::Ge), ("<", BinaryOp::Lt), (">", BinaryOp::Gt)],
+            &[("|", BinaryOp::BitOr), ("&", BinaryOp::BitAnd)],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) => LEVELS[level].iter().find(|(name, _)| name == op),
+                _ => None,
+            };
+            let op = match op {
+                Some((_, op)) => *op,
+                None => return Ok(lhs),
+            };
+
+            self.next();
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Name(name)) => Location::parse(&name)
+                .map(Expr::Location)
+                .ok_or_else(|| format!("Unknown name: {}", name)),
+            Some(Token::Op("!")) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Op("-")) => {
+                Ok(Expr::Binary(BinaryOp::Sub, Box::new(Expr::Number(0)), Box::new(self.unary()?)))
+            },
+            Some(Token::Open) => {
+                let expr = self.binary(0)?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            },
+            Some(Token::OpenBracket) => {
+                let expr = self.binary(0)?;
+                self.expect(Token::CloseBracket)?;
+                Ok(Expr::Memory(Box::new(expr)))
+            },
+            Some(token) => Err(format!("Unexpected token: {:?}", token)),
+            None => Err("Unexpected end of expression".to_owned()),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+        let expr = parser.binary(0)?;
+
+        match parser.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected token: {:?}", token)),
+        }
+    }
+
+    pub fn eval(&self, machine: &Machine) -> Result<i64, String> {
+        Ok(match self {
+            Expr::Number(value) => *value,
+            Expr::Location(location) => location.read(machine),
+            Expr::Memory(address) => {
+                let address = address.eval(machine)?;
+                let ram = machine.get_bus().get_ram();
+                if address < 0 || address as usize >= ram.size() {
+                    return Err(format!("Address out of memory: {:#X}", address));
+                }
+                ram.read(address as Address) as i64
+            },
+            Expr::Not(expr) => (expr.eval(machine)? == 0) as i64,
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(machine)?;
+                // Logical operators short-circuit like in C.
+                match op {
+                    BinaryOp::And if lhs == 0 => return Ok(0),
+                    BinaryOp::Or if lhs != 0 => return Ok(1),
+                    _ => {},
+                }
+                let rhs = rhs.eval(machine)?;
+
+                match op {
+                    BinaryOp::Or | BinaryOp::And => (rhs != 0) as i64,
+                    BinaryOp::Eq => (lhs == rhs) as i64,
+                    BinaryOp::Ne => (lhs != rhs) as i64,
+                    BinaryOp::Lt => (lhs < rhs) as i64,
+                    BinaryOp::Le => (lhs <= rhs) as i64,
+                    BinaryOp::Gt => (lhs > rhs) as i64,
+                    BinaryOp::Ge => (lhs >= rhs) as i64,
+                    BinaryOp::Add => lhs.checked_add(rhs).ok_or("Arithmetic overflow")?,
+                    BinaryOp::Sub => lhs.checked_sub(rhs).ok_or("Arithmetic overflow")?,
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::BitOr => lhs | rhs,
+                }
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::ROM;
+    use crate::specs::Platform;
+    use crate::watcher::Watcher;
+
+    fn machine() -> Machine {
+        // LD V3, 0x05; LD I, 0x310; JP 0x204
+        let rom = ROM::from(vec![0x63, 0x05, 0xA3, 0x10, 0x12, 0x04]);
+        let mut machine = Machine::new(rom, Watcher::new(), 0, Platform::Chip8).unwrap();
+        machine.step();
+        machine.step();
+        machine
+    }
+
+    fn eval(text: &str) -> Result<i64, String> {
+        Expr::parse(text)?.eval(&machine())
+    }
+
+    #[test]
+    fn test_conditions() {
+        assert_eq!(eval("V3 == 5 && I > 0x300"), Ok(1));
+        assert_eq!(eval("v3 != 5 || pc == 0x205"), Ok(0));
+        assert_eq!(eval("!(V3 < 2) && PC == 0x204"), Ok(1));
+        assert_eq!(eval("[0x200] == 0x63 && [PC + 1] == 0x04"), Ok(1));
+        assert_eq!(eval("1 + 2 - -3"), Ok(6));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(eval("V3 ==").is_err());
+        assert!(eval("VG == 1").is_err());
+        assert!(eval("(V3 == 1").is_err());
+        assert!(eval("[0x10000]").is_err());
+        assert!(eval("V3 $ 1").is_err());
+        assert!(eval("0x7FFFFFFFFFFFFFFF + 1 == 0").is_err());
+        assert!(eval("-0x7FFFFFFFFFFFFFFF - 2").is_err());
+    }
+
+    #[test]
+    fn test_assignments() {
+        let mut machine = machine();
+
+        assert_eq!(Location::PC.write(&mut machine, 0xFFE), Ok(()));
+        assert_eq!(machine.get_cpu().get_pc(), 0xFFE);
+        assert_eq!(Location::I.write(&mut machine, 0xFFF), Ok(()));
+        assert_eq!(Location::Register(3).write(&mut machine, 0xFF), Ok(()));
+
+        assert!(Location::PC.write(&mut machine, 0xFFF).is_err());
+        assert!(Location::PC.write(&mut machine, 0xFFFF).is_err());
+        assert!(Location::I.write(&mut machine, 0x1000).is_err());
+        assert!(Location::Register(3).write(&mut machine, 0x100).is_err());
+        assert!(Location::DT.write(&mut machine, -1).is_err());
+        assert!(Location::SP.write(&mut machine, 0).is_err());
+        assert_eq!(machine.get_cpu().get_pc(), 0xFFE);
+
+        let rom = ROM::from(vec![0x12, 0x00]);
+        let mut machine = Machine::new(rom, Watcher::new(), 0, Platform::XoChip).unwrap();
+        assert_eq!(Location::PC.write(&mut machine, 0xFFFE), Ok(()));
+        assert!(Location::PC.write(&mut machine, 0xFFFF).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use crate::display::FrameBuffer;
+use crate::palette::{Color, Palette};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    Off,
+    // Lit pixels fade out, keeping this fraction of their intensity per frame.
+    Phosphor { decay: f32 },
+    // Pixels lit in any of the last frames stay lit.
+    Merge { frames: usize },
+}
+
+// Only works on what is presented, the framebuffer is left untouched. Frames
+// are presented less often than they are emulated under fast-forward, so
+// both filters count in emulated frames.
+pub struct DisplayFilter {
+    mode: FilterMode,
+    size: (usize, usize),
+    // Presented pixels and how many emulated frames ago they were shown.
+    history: VecDeque<(usize, Vec<usize>)>,
+    glow: Vec<[f32; 3]>,
+}
+
+fn channels(color: Color) -> [f32; 3] {
+    [(color >> 16 & 0xFF) as f32, (color >> 8 & 0xFF) as f32, (color & 0xFF) as f32]
+}
+
+fn to_color(channels: [f32; 3]) -> Color {
+    channels.iter().fold(0, |color, channel| (color << 8) | channel.round() as Color)
+}
+
+impl DisplayFilter {
+    pub fn new(mode: FilterMode) -> Self {
+        DisplayFilter {
+            mode,
+            size: (0, 0),
+            history: VecDeque::new(),
+            glow: Vec::new(),
+        }
+    }
+
+    fn pixels(frame_buffer: &FrameBuffer) -> Vec<usize> {
+        let (width, height) = (frame_buffer.width(), frame_buffer.height());
+
+        (0..width * height).map(|idx| frame_buffer.pixel((idx % width, idx / width))).collect()
+    }
+
+    fn merge(&mut self, pixels: Vec<usize>, frames: usize, elapsed: usize, palette: &Palette) -> Vec<Color> {
+        if elapsed == 0 {
+            // Nothing ran since the last present, e.g. while paused.
+            self.history.pop_front();
+        }
+        for (age, _) in self.history.iter_mut() {
+            *age += elapsed;
+        }
+        self.history.retain(|(age, _)| *age < frames.max(1));
+        self.history.push_front((0, pixels));
+
+        (0..self.history[0].1.len()).map(|idx| {
+            palette.color(self.history.iter().fold(0, |color, (_, pixels)| color | pixels[idx]))
+        }).collect()
+    }
+
+    fn phosphor(&mut self, pixels: Vec<usize>, decay: f32, elapsed: usize, palette: &Palette) -> Vec<Color> {
+        let decay = decay.powi(elapsed as i32);
+        let background = channels(palette.color(0));
+
+        if self.glow.len() != pixels.len() {
+            self.glow = vec![background; pixels.len()];
+        }
+
+        self.glow.iter_mut().zip(pixels).map(|(glow, pixel)| {
+            let target = channels(palette.color(pixel));
+
+            for channel in 0..3 {
+                let faded = background[channel] + (glow[channel] - background[channel]) * decay;
+                // The screen lights up instantly but only fades out slowly.
+                glow[channel] = if (target[channel] - background[channel]).abs()
+                                   >= (faded - background[channel]).abs() {
+                    target[channel]
+                } else {
+                    faded
+                };
+            }
+
+            to_color(*glow)
+        }).collect()
+    }
+
+    // `elapsed` is the number of frames emulated since the last call.
+    pub fn apply(&mut self, frame_buffer: &FrameBuffer, palette: &Palette, elapsed: usize) -> Vec<Color> {
+        let size = (frame_buffer.width(), frame_buffer.height());
+        if size != self.size {
+            self.size = size;
+            self.history.clear();
+            self.glow.clear();
+        }
+
+        let pixels = DisplayFilter::pixels(frame_buffer);
+        match self.mode {
+            FilterMode::Off => pixels.into_iter().map(|pixel| palette.color(pixel)).collect(),
+            FilterMode::Merge { frames } => self.merge(pixels, frames, elapsed, palette),
+            FilterMode::Phosphor { decay } => self.phosphor(pixels, decay.clamp(0.0, 1.0), elapsed, palette),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_merge() {
+        let mut filter = DisplayFilter::new(FilterMode::Merge { frames: 2 });
+        let palette = Palette::default();
+        let mut buff = FrameBuffer::new();
+
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0xFFFFFF);
+
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0xFFFFFF);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0x000000);
+        assert!(!buff.read((0, 0)));
+    }
+
+    #[test]
+    fn test_phosphor() {
+        let mut filter = DisplayFilter::new(FilterMode::Phosphor { decay: 0.5 });
+        let palette = Palette::default();
+        let mut buff = FrameBuffer::new();
+
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0xFFFFFF);
+
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0x808080);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0x404040);
+
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0xFFFFFF);
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        let palette = Palette::default();
+        let mut buff = FrameBuffer::new();
+
+        // Frames emulated 8 frames apart are not merged.
+        let mut filter = DisplayFilter::new(FilterMode::Merge { frames: 2 });
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 8)[0], 0xFFFFFF);
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 8)[0], 0x000000);
+
+        // A pause keeps the merged pixels as they were.
+        buff.write((0, 0), true);
+        filter.apply(&buff, &palette, 1);
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0xFFFFFF);
+        assert_eq!(filter.apply(&buff, &palette, 0)[0], 0xFFFFFF);
+
+        // The glow fades by the number of frames emulated.
+        let mut filter = DisplayFilter::new(FilterMode::Phosphor { decay: 0.5 });
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 1)[0], 0xFFFFFF);
+        buff.write((0, 0), true);
+        assert_eq!(filter.apply(&buff, &palette, 0)[0], 0xFFFFFF);
+        assert_eq!(filter.apply(&buff, &palette, 2)[0], 0x404040);
+    }
+}
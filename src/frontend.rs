@@ -0,0 +1,142 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::display::FrameBuffer;
+use crate::input::KeypadState;
+use crate::machine::Machine;
+use crate::scheduler::Scheduler;
+use crate::screenshot::Screenshot;
+
+const FAST_FORWARD_FRAMES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    TogglePause,
+    Advance,
+    Reset,
+    Faster,
+    Slower,
+    Screenshot,
+}
+
+#[derive(Default)]
+pub struct Controls {
+    pub keys: KeypadState,
+    pub commands: Vec<Command>,
+    pub fast_forward: bool,
+    pub quit: bool,
+}
+
+pub struct Status {
+    pub instructions_per_frame: usize,
+    pub paused: bool,
+    pub frames: usize,
+}
+
+pub trait Frontend {
+    fn poll(&mut self) -> Controls;
+    fn present(&mut self, frame_buffer: &FrameBuffer, status: &Status);
+}
+
+fn change_speed(machine: &mut Machine, delta: isize) {
+    // The instruction rate is part of a movie, changing it would break
+    // replays.
+    if machine.has_movie() {
+        return;
+    }
+
+    let ipf = machine.get_instructions_per_frame() as isize + delta;
+    machine.set_instructions_per_frame(ipf.max(1) as usize);
+}
+
+// The frame counter restarts on reset, so earlier shots of the same frame
+// get a numbered suffix instead of being overwritten.
+fn screenshot_path<E: Fn(&Path) -> bool>(frame: u64, exists: E) -> PathBuf {
+    let mut path = PathBuf::from(format!("chip8-{}.png", frame));
+    let mut count = 1;
+
+    while exists(&path) {
+        path = PathBuf::from(format!("chip8-{}-{}.png", frame, count));
+        count += 1;
+    }
+    path
+}
+
+fn take_screenshot(machine: &Machine, screenshot: &Screenshot) {
+    let path = screenshot_path(machine.get_frame(), Path::exists);
+
+    if let Err(err) = screenshot.save(machine.get_bus().get_frame_buffer(), &path) {
+        eprintln!("Unable to save {}: {}", path.display(), err);
+    }
+}
+
+pub fn run<F: Frontend>(frontend: &mut F, machine: &mut Machine, screenshot: &Screenshot) {
+    let mut scheduler = Scheduler::realtime();
+    let mut paused = false;
+
+    loop {
+        let controls = frontend.poll();
+        if controls.quit || machine.has_exited() {
+            break;
+        }
+
+        machine.set_keys(controls.keys);
+
+        let mut advance = false;
+        for command in controls.commands {
+            match command {
+                Command::TogglePause => paused = !paused,
+                Command::Advance => advance = true,
+                Command::Reset => machine.reset(),
+                Command::Faster => change_speed(machine, 1),
+                Command::Slower => change_speed(machine, -1),
+                Command::Screenshot => take_screenshot(machine, screenshot),
+            }
+        }
+
+        let frames = if paused {
+            advance as usize
+        } else if controls.fast_forward {
+            FAST_FORWARD_FRAMES
+        } else {
+            1
+        };
+
+        scheduler.run_frames(machine, frames);
+
+        let status = Status {
+            instructions_per_frame: machine.get_instructions_per_frame(),
+            paused,
+            frames,
+        };
+        frontend.present(machine.get_bus().get_frame_buffer(), &status);
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Chip8 - {} ipf", self.instructions_per_frame)?;
+
+        if self.paused {
+            write!(f, " - paused")?;
+        } else if self.frames > 1 {
+            write!(f, " - fast-forward x{}", self.frames)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screenshot_path() {
+        assert_eq!(screenshot_path(12, |_| false), PathBuf::from("chip8-12.png"));
+
+        let taken = [PathBuf::from("chip8-12.png"), PathBuf::from("chip8-12-1.png")];
+        assert_eq!(screenshot_path(12, |path| taken.iter().any(|taken| taken == path)),
+                   PathBuf::from("chip8-12-2.png"));
+    }
+}
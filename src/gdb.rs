@@ -0,0 +1,513 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::expr::Location;
+use crate::machine::Machine;
+use crate::specs::{Address, Byte, REGISTERS_COUNT};
+
+// Server side of the GDB remote serial protocol, e.g. `target remote :1234`.
+
+// Instructions run between two checks for an interrupt from GDB.
+const INTERRUPT_CHECK: usize = 1000;
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const PACKET_SIZE: usize = 0x1000;
+
+// V0 to VF, then I, PC, SP, DT and ST.
+const GDB_REGISTERS: usize = REGISTERS_COUNT + 5;
+
+static TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+enum Action {
+    Reply(String),
+    Resume(bool),
+    Detach,
+    Kill,
+}
+
+fn register(number: usize) -> Option<(Location, usize)> {
+    match number {
+        index if index < REGISTERS_COUNT => Some((Location::Register(index as u8), 1)),
+        16 => Some((Location::I, 2)),
+        17 => Some((Location::PC, 2)),
+        18 => Some((Location::SP, 1)),
+        19 => Some((Location::DT, 1)),
+        20 => Some((Location::ST, 1)),
+        _ => None,
+    }
+}
+
+fn encode_hex(bytes: &[Byte]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<Byte>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len()).step_by(2)
+                   .map(|idx| text.get(idx..idx + 2).and_then(|hex| Byte::from_str_radix(hex, 16).ok()))
+                   .collect()
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |sum, byte| sum.wrapping_add(byte))
+}
+
+fn error() -> Action {
+    Action::Reply("E01".to_owned())
+}
+
+pub struct GdbStub {
+    machine: Machine,
+    breakpoints: BTreeSet<Address>,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    pub fn new(machine: Machine) -> Self {
+        GdbStub {
+            machine,
+            breakpoints: BTreeSet::new(),
+            no_ack: false,
+        }
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    // Registers are sent in target byte order, which is little endian here.
+    fn read_register(&self, number: usize) -> Option<String> {
+        let (location, size) = register(number)?;
+        let value = location.read(&self.machine) as u16;
+
+        Some(encode_hex(&value.to_le_bytes()[..size]))
+    }
+
+    fn write_register(&mut self, number: usize, hex: &str) -> Option<()> {
+        let (location, size) = register(number)?;
+        let bytes = decode_hex(hex).filter(|bytes| bytes.len() == size)?;
+        let value = bytes.iter().rev().fold(0, |value, byte| (value << 8) | *byte as i64);
+
+        // GDB writes every register back at once, SP is only accepted when
+        // it is left as is.
+        if location == Location::SP {
+            return (location.read(&self.machine) == value).then_some(());
+        }
+        location.write(&mut self.machine, value).ok()
+    }
+
+    fn memory_range(&self, args: &str) -> Option<(Address, usize)> {
+        let (address, length) = args.split_once(',')?;
+        let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+
+        if address.checked_add(length)? > self.machine.get_bus().get_ram().size() {
+            return None;
+        }
+        Some((address as Address, length))
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (address, length) = self.memory_range(args)?;
+        let ram = self.machine.get_bus().get_ram();
+
+        Some(encode_hex(ram.slice(address, length as Address)))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<()> {
+        let (range, data) = args.split_once(':')?;
+        let (address, length) = self.memory_range(range)?;
+        let bytes = decode_hex(data).filter(|bytes| bytes.len() == length)?;
+
+        self.machine.get_bus_mut().write_bytes(address, &bytes);
+        Some(())
+    }
+
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> Action {
+        let mut fields = args.split(',');
+        let address = match (fields.next(), fields.next().and_then(parse_hex)) {
+            // Software and hardware breakpoints are the same thing here.
+            (Some("0"), Some(address)) | (Some("1"), Some(address)) => address as Address,
+            (Some(_), Some(_)) => return Action::Reply(String::new()),
+            _ => return error(),
+        };
+
+        if insert {
+            self.breakpoints.insert(address);
+        } else {
+            self.breakpoints.remove(&address);
+        }
+        Action::Reply("OK".to_owned())
+    }
+
+    fn query(&mut self, query: &str) -> Action {
+        if let Some(args) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let (offset, length) = match args.split_once(',') {
+                Some((offset, length)) => (parse_hex(offset), parse_hex(length)),
+                None => return error(),
+            };
+            return match (offset, length) {
+                (Some(offset), Some(length)) => {
+                    let chunk = TARGET_XML.get(offset..).unwrap_or("");
+                    if chunk.len() > length {
+                        Action::Reply(format!("m{}", &chunk[..length]))
+                    } else {
+                        Action::Reply(format!("l{}", chunk))
+                    }
+                },
+                _ => error(),
+            };
+        }
+
+        let reply = match query.split(':').next().unwrap_or("") {
+            "Supported" => format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE),
+            "Attached" => "1".to_owned(),
+            "C" => "QC1".to_owned(),
+            "fThreadInfo" => "m1".to_owned(),
+            "sThreadInfo" => "l".to_owned(),
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn handle(&mut self, packet: &str) -> Action {
+        let mut chars = packet.chars();
+        let command = match chars.next() {
+            Some(command) => command,
+            None => return Action::Reply(String::new()),
+        };
+        let args = chars.as_str();
+
+        match command {
+            '?' => Action::Reply(self.stop_reply(SIGTRAP)),
+            'g' => Action::Reply((0..GDB_REGISTERS).filter_map(|number| self.read_register(number)).collect()),
+            'G' => {
+                let mut rest = args;
+                for number in 0..GDB_REGISTERS {
+                    let size = register(number).map_or(0, |(_, size)| size * 2);
+                    let hex = match rest.get(..size) {
+                        Some(hex) => hex,
+                        None => return error(),
+                    };
+                    if self.write_register(number, hex).is_none() {
+                        return error();
+                    }
+                    rest = &rest[size..];
+                }
+                Action::Reply("OK".to_owned())
+            },
+            'p' => match parse_hex(args).and_then(|number| self.read_register(number)) {
+                Some(value) => Action::Reply(value),
+                None => error(),
+            },
+            'P' => {
+                let written = args.split_once('=').and_then(|(number, value)| {
+                    self.write_register(parse_hex(number)?, value)
+                });
+                match written {
+                    Some(()) => Action::Reply("OK".to_owned()),
+                    None => error(),
+                }
+            },
+            'm' => match self.read_memory(args) {
+                Some(data) => Action::Reply(data),
+                None => error(),
+            },
+            'M' => match self.write_memory(args) {
+                Some(()) => Action::Reply("OK".to_owned()),
+                None => error(),
+            },
+            'Z' => self.set_breakpoint(args, true),
+            'z' => self.set_breakpoint(args, false),
+            's' | 'c' => {
+                // Resuming elsewhere is checked like writing PC.
+                if !args.is_empty() {
+                    let moved = parse_hex(args).and_then(|address| {
+                        Location::PC.write(&mut self.machine, address as i64).ok()
+                    });
+                    if moved.is_none() {
+                        return error();
+                    }
+                }
+                Action::Resume(command == 's')
+            },
+            'D' => Action::Detach,
+            'k' => Action::Kill,
+            'H' | 'T' => Action::Reply("OK".to_owned()),
+            'q' => self.query(args),
+            'Q' if args == "StartNoAckMode" => {
+                self.no_ack = true;
+                Action::Reply("OK".to_owned())
+            },
+            _ => Action::Reply(String::new()),
+        }
+    }
+
+    fn stop_reply(&self, signal: u8) -> String {
+        if self.machine.has_exited() {
+            "W00".to_owned()
+        } else {
+            format!("S{:02x}", signal)
+        }
+    }
+
+    fn interrupted(stream: &mut TcpStream) -> io::Result<bool> {
+        let mut byte = [0];
+
+        stream.set_nonblocking(true)?;
+        let read = stream.read(&mut byte);
+        stream.set_nonblocking(false)?;
+
+        match read {
+            Ok(1) => Ok(byte[0] == 0x03),
+            Ok(_) => Ok(false),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn resume(&mut self, stream: &mut TcpStream, step: bool) -> io::Result<String> {
+        let mut executed = 0;
+
+        loop {
+            let pc = self.machine.step();
+            executed += 1;
+
+            if step || self.machine.has_exited() || self.breakpoints.contains(&pc) {
+                return Ok(self.stop_reply(SIGTRAP));
+            }
+            if executed % INTERRUPT_CHECK == 0 && GdbStub::interrupted(stream)? {
+                return Ok(self.stop_reply(SIGINT));
+            }
+        }
+    }
+
+    fn read_byte(stream: &mut TcpStream) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+
+        match stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // Returns `None` once GDB has closed the connection.
+    fn read_packet(&mut self, stream: &mut TcpStream) -> io::Result<Option<String>> {
+        loop {
+            // Acknowledgements and interrupts sent while stopped are skipped.
+            match GdbStub::read_byte(stream)? {
+                Some(b'$') => {},
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match GdbStub::read_byte(stream)? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+
+            let mut sum = [0; 2];
+            stream.read_exact(&mut sum)?;
+            let data = String::from_utf8_lossy(&data).into_owned();
+            let valid = std::str::from_utf8(&sum).ok()
+                                                 .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                                                 == Some(checksum(&data));
+
+            if !self.no_ack {
+                stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    fn send_packet(&self, stream: &mut TcpStream, data: &str) -> io::Result<()> {
+        write!(stream, "${}#{:02x}", data, checksum(data))?;
+        stream.flush()
+    }
+
+    pub fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+
+        while let Some(packet) = self.read_packet(&mut stream)? {
+            let reply = match self.handle(&packet) {
+                Action::Reply(reply) => reply,
+                Action::Resume(step) => self.resume(&mut stream, step)?,
+                Action::Detach => {
+                    self.send_packet(&mut stream, "OK")?;
+                    break;
+                },
+                // GDB does not wait for a reply to a kill request.
+                Action::Kill => break,
+            };
+            self.send_packet(&mut stream, &reply)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Waits for a single GDB connection, `:1234` listens on the loopback
+// interface only.
+pub fn listen(address: &str, machine: Machine) -> io::Result<Machine> {
+    let address = match address.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{}", port),
+        None => address.to_owned(),
+    };
+    let listener = TcpListener::bind(&address)?;
+
+    println!("Waiting for GDB on {}.", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    println!("GDB connected from {}.", peer);
+
+    let mut stub = GdbStub::new(machine);
+    stub.serve(stream)?;
+    Ok(stub.into_machine())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::ROM;
+    use crate::specs::Platform;
+    use crate::watcher::Watcher;
+    use std::thread;
+
+    // Sends a packet and returns the reply, like GDB would.
+    fn exchange(stream: &mut TcpStream, packet: &str) -> String {
+        write!(stream, "${}#{:02x}", packet, checksum(packet)).unwrap();
+
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            match byte[0] {
+                b'+' if reply.is_empty() => continue,
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let mut sum = [0; 2];
+        stream.read_exact(&mut sum).unwrap();
+        stream.write_all(b"+").unwrap();
+
+        assert_eq!(reply[0], b'$');
+        String::from_utf8(reply[1..].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(encode_hex(&[0x00, 0xFF, 0x81]), "00ff81");
+        assert_eq!(decode_hex("00ff81"), Some(vec![0x00, 0xFF, 0x81]));
+        assert_eq!(decode_hex("0f1"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(checksum("OK"), 0x9a);
+    }
+
+    #[test]
+    fn test_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut replies = Vec::new();
+
+            for packet in &["qSupported:swbreak+", "?", "p11", "P3=10", "p3", "m200,4",
+                            "M300,2:ff81", "m300,2", "mfff,2", "Z0,204,2", "c", "p11",
+                            "z0,204,2", "s", "g", "qXfer:features:read:target.xml:0,10", "Z2,300,1",
+                            "P11=ffff", "mffffffffffffffff,2", "cffff"] {
+                replies.push(exchange(&mut stream, packet));
+            }
+            exchange(&mut stream, "D");
+            replies
+        });
+
+        // LD V3, 0x00; ADD V3, 0x01; JP 0x202
+        let rom = ROM::from(vec![0x63, 0x00, 0x73, 0x01, 0x12, 0x02]);
+        let machine = Machine::new(rom, Watcher::new(), 0, Platform::Chip8).unwrap();
+        let mut stub = GdbStub::new(machine);
+        stub.serve(listener.accept().unwrap().0).unwrap();
+
+        let replies = client.join().unwrap();
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert_eq!(&replies[1..8], &["S05", "0002", "OK", "10", "63007301", "OK", "ff81"]);
+        assert_eq!(replies[8], "E01");
+        // Runs LD V3, 0x00 and ADD V3, 0x01 before stopping on 0x204.
+        assert_eq!(&replies[9..12], &["OK", "S05", "0402"]);
+        assert_eq!(&replies[12..14], &["OK", "S05"]);
+        assert_eq!(replies[14], "0000000100000000000000000000000000000202000000");
+        assert_eq!(replies[15], "m<?xml version=\"1");
+        assert_eq!(replies[16], "");
+        // PC must stay within the 4 KiB of memory.
+        assert_eq!(replies[17], "E01");
+        assert_eq!(replies[18], "E01");
+        assert_eq!(replies[19], "E01");
+        assert_eq!(stub.into_machine().get_cpu().get_pc(), 0x202);
+    }
+
+    #[test]
+    fn test_kill() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "$k#{:02x}", checksum("k")).unwrap();
+
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let rom = ROM::from(vec![0x12, 0x00]);
+        let machine = Machine::new(rom, Watcher::new(), 0, Platform::Chip8).unwrap();
+        let mut stub = GdbStub::new(machine);
+        stub.serve(listener.accept().unwrap().0).unwrap();
+        drop(stub);
+
+        // Only the acknowledgement, no reply packet.
+        assert_eq!(client.join().unwrap(), b"+");
+    }
+}
@@ -0,0 +1,29 @@
+use crate::machine::Machine;
+use crate::scheduler::Scheduler;
+
+pub struct Headless {
+    machine: Machine,
+    scheduler: Scheduler,
+    frames: u64,
+}
+
+impl Headless {
+    pub fn new(machine: Machine, frames: u64) -> Self {
+        Headless {
+            machine,
+            scheduler: Scheduler::unthrottled(),
+            frames,
+        }
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    pub fn run(&mut self) {
+        // Replayed resets restart the frame count but not the movie's.
+        while self.machine.get_movie_frame() < self.frames && !self.machine.has_exited() {
+            self.scheduler.run_frame(&mut self.machine);
+        }
+    }
+}
@@ -1,19 +1,25 @@
+pub type KeypadState = u16;
+
 pub struct Keyboard {
-    key_pressed: Option<u8>,
+    state: KeypadState,
 }
 
 impl Keyboard {
     pub fn new() -> Self {
         Keyboard {
-            key_pressed: None,
+            state: 0x0,
         }
     }
 
-    pub fn set_key_pressed(&mut self, key: Option<u8>) {
-        self.key_pressed = key;
+    pub fn set_state(&mut self, state: KeypadState) {
+        self.state = state;
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        key < 16 && self.state & (1 << key) != 0
     }
 
     pub fn get_key_pressed(&self) -> Option<u8> {
-        self.key_pressed
+        (0..16).find(|key| self.is_pressed(*key))
     }
 }
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::specs::Address;
+
+pub mod error {
+    use std::fmt;
+    use std::io;
+
+    #[derive(Debug)]
+    pub enum LineError {
+        IOError(io::Error),
+        ParseError(usize, String),
+    }
+
+    impl From<io::Error> for LineError {
+        fn from(err: io::Error) -> Self {
+            LineError::IOError(err)
+        }
+    }
+
+    impl fmt::Display for LineError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                LineError::IOError(err) => write!(f, "I/O error: {}", err),
+                LineError::ParseError(line, msg) => {
+                    write!(f, "line table line {}: {}", line, msg)
+                },
+            }
+        }
+    }
+}
+
+use error::LineError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: usize,
+}
+
+// Line tables map the address of each assembled instruction to its source,
+// one `<hex address> <line> <file>` entry per line. The debugger picks up
+// tables saved next to the ROM with a `.lines` extension.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineTable {
+    lines: BTreeMap<Address, SourceLine>,
+}
+
+// Editors send absolute paths while tables usually hold relative ones.
+fn same_file(left: &str, right: &str) -> bool {
+    Path::new(left).ends_with(right) || Path::new(right).ends_with(left)
+}
+
+impl LineTable {
+    pub fn new() -> Self {
+        LineTable::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, LineError> {
+        let mut table = LineTable::new();
+
+        let lines = text.lines().enumerate()
+                        .map(|(idx, line)| (idx + 1, line.trim()))
+                        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        for (idx, entry) in lines {
+            let mut tokens = entry.splitn(3, char::is_whitespace);
+            let (address, line, file) = match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some(address), Some(line), Some(file)) => (address, line, file.trim()),
+                _ => return Err(LineError::ParseError(idx, "expected an address, a line and a file".to_owned())),
+            };
+            let address = Address::from_str_radix(address.trim_start_matches("0x"), 16)
+                .map_err(|_| LineError::ParseError(idx, format!("invalid address: {}", address)))?;
+            let line = line.parse()
+                .map_err(|_| LineError::ParseError(idx, format!("invalid line: {}", line)))?;
+
+            table.insert(address, file, line);
+        }
+
+        Ok(table)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, LineError> {
+        LineTable::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn insert(&mut self, address: Address, file: &str, line: usize) {
+        self.lines.insert(address, SourceLine { file: file.to_owned(), line });
+    }
+
+    pub fn files(&self) -> BTreeSet<&str> {
+        self.lines.values().map(|source| source.file.as_str()).collect()
+    }
+
+    pub fn lookup(&self, address: Address) -> Option<&SourceLine> {
+        self.lines.get(&address)
+    }
+
+    // Returns the first instruction of the line, or of the next line with
+    // code, like GDB does for breakpoints on comments or blank lines.
+    pub fn address_of(&self, file: &str, line: usize) -> Option<(Address, usize)> {
+        self.lines.iter()
+            .filter(|(_, source)| same_file(&source.file, file) && source.line >= line)
+            .min_by_key(|(address, source)| (source.line, **address))
+            .map(|(address, source)| (*address, source.line))
+    }
+}
+
+impl fmt::Display for LineTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (address, source) in &self.lines {
+            writeln!(f, "{:03X} {} {}", address, source.line, source.file)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let table = LineTable::parse("# game.s\n200 3 game.s\n202 3 game.s\n204 7 game.s\n300 2 lib/draw.s\n").unwrap();
+
+        assert_eq!(table.lookup(0x202), Some(&SourceLine { file: "game.s".to_owned(), line: 3 }));
+        assert_eq!(table.lookup(0x206), None);
+        assert_eq!(table.address_of("game.s", 3), Some((0x200, 3)));
+        assert_eq!(table.address_of("/home/me/game/game.s", 5), Some((0x204, 7)));
+        assert_eq!(table.address_of("draw.s", 1), Some((0x300, 2)));
+        assert_eq!(table.address_of("game.s", 8), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(LineTable::parse("200 3"), Err(LineError::ParseError(1, _))));
+        assert!(matches!(LineTable::parse("200 three game.s"), Err(LineError::ParseError(1, _))));
+        assert!(matches!(LineTable::parse("200 1 a.s\nxyz 1 a.s"), Err(LineError::ParseError(2, _))));
+    }
+}
@@ -0,0 +1,379 @@
+use crate::cpu::CPU;
+use crate::bus::Bus;
+use crate::watcher::Watcher;
+use crate::memory::{MainMemory, ROM};
+use crate::memory::error::MemoryError;
+use crate::input::KeypadState;
+use crate::movie::{Movie, Player};
+use crate::audio::Beeper;
+use crate::display::SpriteEdge;
+use crate::recorder::GifRecorder;
+use crate::specs::{Address, Platform};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 8;
+
+pub struct Machine {
+    cpu: CPU,
+    bus: Bus,
+    rom: ROM,
+    platform: Platform,
+    watcher: Watcher,
+    sprite_edge: SpriteEdge,
+
+    instructions_per_frame: usize,
+    frame: u64,
+    cycle: usize,
+    // Frames since the movie started, kept across resets.
+    movie_frame: u64,
+
+    pending_keys: KeypadState,
+    recording: Option<Movie>,
+    player: Option<Player>,
+    audio: Option<Beeper>,
+    gif: Option<GifRecorder<BufWriter<File>>>,
+}
+
+impl Machine {
+    pub fn new(rom: ROM, watcher: Watcher, seed: u64, platform: Platform) -> Result<Self, MemoryError> {
+        let mem = MainMemory::with_rom(&rom, platform)?;
+
+        Ok(Machine {
+            cpu: CPU::new(watcher.clone(), seed, platform),
+            bus: Bus::new(mem, watcher.clone()),
+            rom,
+            platform,
+            watcher,
+            sprite_edge: SpriteEdge::Clip,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            frame: 0,
+            cycle: 0,
+            movie_frame: 0,
+            pending_keys: 0x0,
+            recording: None,
+            player: None,
+            audio: None,
+            gif: None,
+        })
+    }
+
+    pub fn from_movie(rom: ROM, watcher: Watcher, movie: Movie) -> Result<Self, MemoryError> {
+        let mut machine = Machine::new(rom, watcher, movie.seed, movie.platform)?;
+
+        machine.instructions_per_frame = movie.instructions_per_frame;
+        machine.set_sprite_edge(movie.sprite_edge);
+        machine.player = Some(Player::new(movie));
+        Ok(machine)
+    }
+
+    pub fn get_cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn get_cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn reset(&mut self) {
+        self.restart();
+
+        // A recording keeps going and replays the reset at the same point.
+        if let Some(ref mut movie) = self.recording {
+            movie.record_reset(self.movie_frame);
+        }
+        if let Some(ref mut player) = self.player {
+            player.rewind();
+            self.movie_frame = 0;
+        }
+    }
+
+    fn restart(&mut self) {
+        self.cpu.reset();
+        // The ROM already fitted when the machine was created.
+        let mem = MainMemory::with_rom(&self.rom, self.platform).expect("ROM no longer fits in memory");
+        self.bus = Bus::new(mem, self.watcher.clone());
+        self.bus.set_sprite_edge(self.sprite_edge);
+        self.frame = 0;
+        self.cycle = 0;
+    }
+
+    pub fn get_watcher(&self) -> &Watcher {
+        &self.watcher
+    }
+
+    pub fn get_bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn get_bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn get_instructions_per_frame(&self) -> usize {
+        self.instructions_per_frame
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: usize) {
+        self.instructions_per_frame = instructions.max(1);
+    }
+
+    pub fn set_sprite_edge(&mut self, edge: SpriteEdge) {
+        self.sprite_edge = edge;
+        self.bus.set_sprite_edge(edge);
+    }
+
+    pub fn has_movie(&self) -> bool {
+        self.recording.is_some() || self.player.is_some()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.cpu.has_exited()
+    }
+
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn get_movie_frame(&self) -> u64 {
+        self.movie_frame
+    }
+
+    pub fn get_movie_length(&self) -> Option<u64> {
+        self.player.as_ref().map(|player| player.get_movie().length)
+    }
+
+    pub fn start_recording(&mut self, seed: u64) {
+        let mut movie = Movie::new(seed, self.instructions_per_frame, self.platform);
+        movie.sprite_edge = self.sprite_edge;
+        self.recording = Some(movie);
+        self.movie_frame = 0;
+    }
+
+    pub fn get_recording(&self) -> Option<&Movie> {
+        self.recording.as_ref()
+    }
+
+    pub fn set_audio(&mut self, beeper: Beeper) {
+        self.audio = Some(beeper);
+    }
+
+    pub fn finish_audio(&mut self) -> io::Result<()> {
+        match self.audio {
+            Some(ref mut beeper) => beeper.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_gif_recorder(&mut self, recorder: GifRecorder<BufWriter<File>>) {
+        self.gif = Some(recorder);
+    }
+
+    pub fn finish_gif(&mut self) -> io::Result<()> {
+        match self.gif {
+            Some(ref mut recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_keys(&mut self, keys: KeypadState) {
+        self.pending_keys = keys;
+    }
+
+    fn begin_frame(&mut self) {
+        let frame = self.movie_frame;
+        if self.player.as_ref().is_some_and(|player| player.get_movie().has_reset(frame)) {
+            self.restart();
+        }
+
+        let keys = match self.player {
+            Some(ref mut player) if !player.is_finished(frame) => player.keys_at(frame),
+            _ => self.pending_keys,
+        };
+
+        if let Some(ref mut movie) = self.recording {
+            movie.record(frame, keys);
+        }
+        self.bus.set_keypad_state(keys);
+    }
+
+    fn end_frame(&mut self) {
+        if let Some(ref mut beeper) = self.audio {
+            let pattern = self.cpu.get_audio_pattern();
+            beeper.render_frame(self.cpu.get_sound_timer() > 0, pattern.as_ref());
+        }
+
+        if let Some(ref mut recorder) = self.gif {
+            recorder.capture(self.bus.get_frame_buffer());
+        }
+
+        self.cpu.timer_decrement();
+        self.frame += 1;
+        self.movie_frame += 1;
+        self.cycle = 0;
+
+        if let Some(ref mut movie) = self.recording {
+            movie.length = self.movie_frame;
+        }
+    }
+
+    pub fn step(&mut self) -> Address {
+        if self.cycle == 0 {
+            self.begin_frame();
+        }
+
+        let pc = self.cpu.tick(&mut self.bus);
+
+        self.cycle += 1;
+        if self.cycle == self.instructions_per_frame {
+            self.end_frame();
+        }
+
+        pc
+    }
+
+    pub fn run_frame(&mut self) {
+        let frame = self.frame;
+
+        while self.frame == frame {
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use std::path::Path;
+
+    fn load_rom(name: &str) -> ROM {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("roms").join(name);
+        ROM::from_file(&path).unwrap()
+    }
+
+    #[test]
+    fn test_timers_tick_once_per_frame() {
+        // LD V0, 0x3C; LD DT, V0; JP 0x204
+        let rom = ROM::from(vec![0x60, 0x3C, 0xF0, 0x15, 0x12, 0x04]);
+        let mut machine = Machine::new(rom, Watcher::new(), 0, Platform::Chip8).unwrap();
+        machine.set_instructions_per_frame(3);
+
+        for _ in 0..10 {
+            machine.run_frame();
+        }
+
+        assert_eq!(machine.get_frame(), 10);
+        assert_eq!(machine.get_cpu().get_delay_timer(), 0x32);
+    }
+
+    #[test]
+    fn test_superchip_program() {
+        // HIGH; LD V0, 0x08; LD HF, V0; DRW V1, V1, 0; EXIT
+        let rom = ROM::from(vec![0x00, 0xFF, 0x60, 0x08, 0xF0, 0x30, 0xD1, 0x10, 0x00, 0xFD]);
+        let mut machine = Machine::new(rom, Watcher::new(), 0, Platform::SuperChip).unwrap();
+
+        machine.run_frame();
+
+        let frame_buffer = machine.get_bus().get_frame_buffer();
+        assert!(machine.has_exited());
+        assert_eq!(frame_buffer.width(), 128);
+        assert!(frame_buffer.read((2, 0)));
+        assert!(!frame_buffer.read((0, 0)));
+    }
+
+    #[test]
+    fn test_xochip_program() {
+        // LD I, LONG 0x8000; LD V0, 0xC0; LD V1, 0x80; SAVE V0 - V1; PLANE 3;
+        // SE V0, 0xC0; LD I, LONG 0x0000; LOAD V3 - V2; SAVE V2 - V3;
+        // DRW V4, V4, 1; EXIT
+        let rom = ROM::from(vec![
+            0xF0, 0x00, 0x80, 0x00, 0x60, 0xC0, 0x61, 0x80, 0x50, 0x12, 0xF3, 0x01,
+            0x30, 0xC0, 0xF0, 0x00, 0x00, 0x00, 0x53, 0x23, 0x52, 0x32, 0xD4, 0x41,
+            0x00, 0xFD,
+        ]);
+        let mut machine = Machine::new(rom, Watcher::new(), 0, Platform::XoChip).unwrap();
+
+        machine.run_frame();
+        machine.run_frame();
+
+        let bus = machine.get_bus();
+        assert!(machine.has_exited());
+        assert_eq!(bus.get_ram().read(0x8000), 0x80);
+        assert_eq!(bus.get_ram().read(0x8001), 0xC0);
+        assert_eq!(bus.get_frame_buffer().pixel((0, 0)), 0b11);
+        assert_eq!(bus.get_frame_buffer().pixel((1, 0)), 0b10);
+    }
+
+    #[test]
+    fn test_reset_restarts_program() {
+        let mut fresh = Machine::new(load_rom("PONG"), Watcher::new(), 7, Platform::Chip8).unwrap();
+        let mut machine = Machine::new(load_rom("PONG"), Watcher::new(), 7, Platform::Chip8).unwrap();
+
+        for _ in 0..100 {
+            machine.run_frame();
+        }
+        machine.reset();
+        assert_eq!(machine.get_frame(), 0);
+
+        for _ in 0..50 {
+            machine.run_frame();
+            fresh.run_frame();
+        }
+        assert_eq!(format!("{}", machine.get_cpu()), format!("{}", fresh.get_cpu()));
+        assert_eq!(format!("{}", machine.get_bus().get_frame_buffer()),
+                   format!("{}", fresh.get_bus().get_frame_buffer()));
+    }
+
+    #[test]
+    fn test_replay_is_identical() {
+        let mut machine = Machine::new(load_rom("BRIX"), Watcher::new(), 1234, Platform::Chip8).unwrap();
+        machine.start_recording(1234);
+
+        for frame in 0..600 {
+            machine.set_keys(match frame / 50 % 3 {
+                0 => 1 << 0x4,
+                1 => 1 << 0x6,
+                _ => 0x0,
+            });
+            machine.run_frame();
+        }
+
+        let movie = machine.get_recording().unwrap().clone();
+        let mut replay = Machine::from_movie(load_rom("BRIX"), Watcher::new(), movie).unwrap();
+        for _ in 0..600 {
+            replay.run_frame();
+        }
+
+        assert_eq!(format!("{}", replay.get_cpu()), format!("{}", machine.get_cpu()));
+        assert_eq!(format!("{}", replay.get_bus().get_frame_buffer()),
+                   format!("{}", machine.get_bus().get_frame_buffer()));
+    }
+    #[test]
+    fn test_replay_keeps_resets() {
+        let mut machine = Machine::new(load_rom("BRIX"), Watcher::new(), 99, Platform::Chip8).unwrap();
+        machine.start_recording(99);
+
+        for frame in 0..300 {
+            machine.set_keys(if frame % 40 < 20 { 1 << 0x6 } else { 0x0 });
+            machine.run_frame();
+            if frame == 150 {
+                machine.reset();
+            }
+        }
+
+        let movie = machine.get_recording().unwrap().clone();
+        assert_eq!(movie.length, 300);
+
+        let mut replay = Machine::from_movie(load_rom("BRIX"), Watcher::new(), movie).unwrap();
+        while replay.get_movie_frame() < 300 {
+            replay.run_frame();
+        }
+
+        assert_eq!(replay.get_frame(), machine.get_frame());
+        assert_eq!(format!("{}", replay.get_cpu()), format!("{}", machine.get_cpu()));
+        assert_eq!(format!("{}", replay.get_bus().get_frame_buffer()),
+                   format!("{}", machine.get_bus().get_frame_buffer()));
+    }
+}
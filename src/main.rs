@@ -1,19 +1,62 @@
 mod asm;
+mod assembler;
 mod specs;
 mod memory;
 mod cpu;
 mod cli;
 mod debugger;
+mod debugger_tui;
 mod bus;
 mod display;
 mod input;
 mod watcher;
 mod window;
+mod machine;
+mod movie;
+mod headless;
+mod audio;
+mod scheduler;
+mod frontend;
+mod tui;
+mod palette;
+mod screenshot;
+mod recorder;
+mod filter;
+mod expr;
+mod symbols;
+mod gdb;
+mod lines;
+mod dap;
 
 extern crate clap;
 
 use clap::{App, Arg};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+// Lets clap report malformed numbers instead of panicking after parsing.
+fn number<T: FromStr>(value: String) -> Result<(), String> {
+    value.parse::<T>().map(|_| ()).map_err(|_| format!("invalid number: {}", value))
+}
+
+fn palette(value: String) -> Result<(), String> {
+    value.parse::<palette::Palette>().map(|_| ())
+}
+
+fn positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(0) | Err(_) => Err(format!("expected a positive integer: {}", value)),
+        Ok(_) => Ok(()),
+    }
+}
+
+fn fraction(value: String) -> Result<(), String> {
+    match value.parse::<f32>() {
+        Ok(level) if (0.0..=1.0).contains(&level) => Ok(()),
+        _ => Err(format!("expected a number between 0 and 1: {}", value)),
+    }
+}
 
 fn main() {
     let matches = App::new("Chip8")
@@ -25,11 +68,73 @@ fn main() {
                 .about("runs the Chip8 virtual machine")
                 .arg(Arg::from_usage("-g, --debug 'enables debugging mode'"))
                 .arg(Arg::from_usage("-v, --verbose 'enables verbose mode'"))
+                .arg(Arg::from_usage("--headless 'runs without any window'"))
+                .arg(Arg::from_usage("--tui 'renders the screen in the terminal, or runs the full-screen debugger with --debug'"))
+                .arg(Arg::from_usage("--frames [count] 'number of frames to run in headless mode'")
+                     .validator(number::<u64>))
+                .arg(Arg::from_usage("--platform [name] 'machine variant to emulate'")
+                     .possible_values(&["chip8", "schip", "xochip"])
+                     .default_value("schip"))
+                .arg(Arg::from_usage("--sprite-edge [policy] 'clips or wraps sprites crossing the screen edge'")
+                     .possible_values(&["clip", "wrap"])
+                     .default_value("clip"))
+                .arg(Arg::from_usage("--gdb [address] 'waits for a GDB remote connection, e.g. :1234'")
+                     .conflicts_with_all(&["debug", "headless", "tui"]))
+                .arg(Arg::from_usage("--dap [address] 'serves the Debug Adapter Protocol on stdio or a local socket, e.g. :4711'")
+                     .conflicts_with_all(&["debug", "headless", "tui", "gdb"]))
+                .arg(Arg::from_usage("--lines [file] 'loads a line table mapping addresses to source lines, defaults to the ROM with a .lines extension'"))
+                .arg(Arg::from_usage("--symbols [file] 'loads symbol names for the debugger'"))
+                .arg(Arg::from_usage("--ipf [count] 'instructions executed per 60 Hz frame'")
+                     .validator(positive))
+                .arg(Arg::from_usage("--record [movie] 'records keypad input to a movie file, the speed hotkeys are disabled while recording'")
+                     .conflicts_with("play"))
+                .arg(Arg::from_usage("--play [movie] 'replays keypad input from a movie file, at the speed it was recorded with'"))
+                .arg(Arg::from_usage("--wav [file] 'writes the sound output to a WAV file'"))
+                .arg(Arg::from_usage("--screenshot [file] 'saves the last frame as a PNG or PPM image in headless mode'")
+                     .requires("headless"))
+                .arg(Arg::from_usage("--screenshot-scale [factor] 'pixel size of screenshots'")
+                     .default_value("4")
+                     .validator(positive))
+                .arg(Arg::from_usage("--palette [colors] 'palette name (classic, amber, green, octo) or comma separated hex colours'")
+                     .default_value("classic")
+                     .validator(palette))
+                .arg(Arg::from_usage("--palette-file [file] 'loads the palette from a file, overrides --palette'"))
+                .arg(Arg::from_usage("--grid 'draws lines between pixels'"))
+                .arg(Arg::from_usage("--border [size] 'width of the border around the screen, in window pixels'")
+                     .default_value("0")
+                     .validator(number::<usize>))
+                .arg(Arg::from_usage("--scale [factor] 'initial window pixels per screen pixel'")
+                     .default_value("10")
+                     .validator(positive))
+                .arg(Arg::from_usage("--crt 'darkens scanlines like a CRT monitor'"))
+                .arg(Arg::from_usage("--filter [name] 'reduces flicker in the window'")
+                     .possible_values(&["off", "phosphor", "merge"])
+                     .default_value("off"))
+                .arg(Arg::from_usage("--decay [level] 'intensity kept per frame by the phosphor filter'")
+                     .default_value("0.6")
+                     .validator(number::<f32>))
+                .arg(Arg::from_usage("--merge-frames [count] 'frames combined by the merge filter'")
+                     .default_value("2")
+                     .validator(positive))
+                .arg(Arg::from_usage("--gif [file] 'records the screen to an animated GIF'"))
+                .arg(Arg::from_usage("--gif-scale [factor] 'pixel size of the GIF, relative to hires mode'")
+                     .default_value("2")
+                     .validator(positive))
+                .arg(Arg::from_usage("--gif-every [frames] 'records one frame out of this many'")
+                     .default_value("1")
+                     .validator(positive))
+                .arg(Arg::from_usage("--tone [hz] 'frequency of the beep'")
+                     .validator(number::<f32>))
+                .arg(Arg::from_usage("--volume [level] 'volume of the beep, between 0 and 1'")
+                     .validator(fraction))
                 .arg(Arg::from_usage("<rom> 'ROM file to run.'")),
         )
         .subcommand(
             App::new("asm")
                 .about("assembles code to a Chip8 bytecode")
+                .arg(Arg::from_usage("-o, --output [rom] 'ROM file to write, the code with a .ch8 extension by default'"))
+                .arg(Arg::from_usage("--lines [file] 'writes a line table for source-level debugging, \
+                                      `vm --debug` loads <rom>.lines by itself'"))
                 .arg(Arg::from_usage("<code> 'Code to assemble.'")),
         )
         .subcommand(
@@ -40,18 +145,68 @@ fn main() {
         )
         .get_matches();
 
-    if let Some(ref matches) = matches.subcommand_matches("vm") {
+    if let Some(matches) = matches.subcommand_matches("vm") {
         let path = Path::new(matches.value_of("rom").unwrap());
-        let debug_mode = matches.is_present("debug");
-        let verbose = matches.is_present("verbose");
+        let tone = audio::Tone::default();
+        let options = cli::VmOptions {
+            debug: matches.is_present("debug"),
+            verbose: matches.is_present("verbose"),
+            headless: matches.is_present("headless"),
+            tui: matches.is_present("tui"),
+            frames: matches.value_of("frames").map(|frames| frames.parse().unwrap()),
+            platform: matches.value_of("platform").unwrap().parse().unwrap(),
+            sprite_edge: matches.value_of("sprite-edge").unwrap().parse().unwrap(),
+            symbols: matches.value_of("symbols").map(PathBuf::from),
+            gdb: matches.value_of("gdb").map(str::to_owned),
+            dap: matches.value_of("dap").map(str::to_owned),
+            lines: matches.value_of("lines").map(PathBuf::from),
+            instructions_per_frame: matches.value_of("ipf").map(|ipf| ipf.parse().unwrap()),
+            record: matches.value_of("record").map(PathBuf::from),
+            play: matches.value_of("play").map(PathBuf::from),
+            wav: matches.value_of("wav").map(PathBuf::from),
+            screenshot: matches.value_of("screenshot").map(PathBuf::from),
+            screenshot_scale: matches.value_of("screenshot-scale").unwrap().parse().unwrap(),
+            gif: matches.value_of("gif").map(PathBuf::from),
+            gif_scale: matches.value_of("gif-scale").unwrap().parse().unwrap(),
+            gif_interval: matches.value_of("gif-every").unwrap().parse().unwrap(),
+            palette: matches.value_of("palette").unwrap().parse().unwrap(),
+            palette_file: matches.value_of("palette-file").map(PathBuf::from),
+            grid: matches.is_present("grid"),
+            border: matches.value_of("border").unwrap().parse().unwrap(),
+            scale: matches.value_of("scale").unwrap().parse().unwrap(),
+            crt: matches.is_present("crt"),
+            filter: match matches.value_of("filter").unwrap() {
+                "phosphor" => filter::FilterMode::Phosphor {
+                    decay: matches.value_of("decay").unwrap().parse().unwrap(),
+                },
+                "merge" => filter::FilterMode::Merge {
+                    frames: matches.value_of("merge-frames").unwrap().parse().unwrap(),
+                },
+                _ => filter::FilterMode::Off,
+            },
+            tone: audio::Tone {
+                frequency: matches.value_of("tone").map_or(tone.frequency, |hz| hz.parse().unwrap()),
+                volume: matches.value_of("volume").map_or(tone.volume, |level| level.parse().unwrap()),
+            },
+        };
+
+        if let Err(err) = cli::emulate(path, options) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("asm") {
+        let path = Path::new(matches.value_of("code").unwrap());
+        let rom = matches.value_of("output").map_or_else(|| path.with_extension("ch8"), PathBuf::from);
+        let lines = matches.value_of("lines").map(Path::new);
 
-        cli::emulate(&path, debug_mode, verbose).unwrap();
-    } else if let Some(ref _matches) = matches.subcommand_matches("asm") {
-        unimplemented!();
-    } else if let Some(ref matches) = matches.subcommand_matches("dis") {
+        if let Err(err) = cli::assemble(path, &rom, lines) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("dis") {
         let path = Path::new(matches.value_of("rom").unwrap());
         let display_address = matches.is_present("n");
 
-        cli::disassemble(&path, display_address).unwrap();
+        cli::disassemble(path, display_address).unwrap();
     }
 }
@@ -4,7 +4,47 @@ use std::fs::File;
 use std::io;
 use std::io::Read;
 
-use crate::specs::{Byte, MEMORY_SIZE, PROGRAM_BEGIN, Address};
+use crate::specs::{Byte, PROGRAM_BEGIN, BIG_FONT_BEGIN, Address, Platform};
+
+const BIG_FONTSET: [Byte; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+pub mod error {
+    use std::fmt;
+
+    #[derive(Debug)]
+    pub enum MemoryError {
+        RomTooLarge(usize, usize),
+    }
+
+    impl fmt::Display for MemoryError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                MemoryError::RomTooLarge(size, available) => {
+                    write!(f, "ROM of {} bytes does not fit in the {} bytes of program memory", size, available)
+                },
+            }
+        }
+    }
+}
+
+use error::MemoryError;
 
 pub trait Memory {
     type Address;
@@ -15,80 +55,114 @@ pub trait Memory {
 }
 
 pub struct MainMemory {
-    mem: [Byte; MEMORY_SIZE]
+    mem: Vec<Byte>
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct ROM {
     data: Vec<Byte>,
 }
 
 impl MainMemory {
-    pub fn with_rom(rom: ROM) -> Self {
-        let mut mem = [0x0; MEMORY_SIZE];
+    #[cfg(test)]
+    pub fn new() -> Self {
+        MainMemory::with_size(Platform::Chip8.memory_size())
+    }
+
+    pub fn with_size(size: usize) -> Self {
+        let mut mem = MainMemory { mem: vec![0x0; size] };
+        mem.load_fontset();
+        mem
+    }
+
+    pub fn with_rom(rom: &ROM, platform: Platform) -> Result<Self, MemoryError> {
+        let mut mem = MainMemory::with_size(platform.memory_size());
+
+        let available = mem.size() - PROGRAM_BEGIN;
+        if rom.size() > available {
+            return Err(MemoryError::RomTooLarge(rom.size(), available));
+        }
 
         let inf_bound = PROGRAM_BEGIN;
         let sup_bound = PROGRAM_BEGIN + rom.size();
-        &mem[inf_bound..sup_bound].copy_from_slice(rom.bytes());
+        mem.mem[inf_bound..sup_bound].copy_from_slice(rom.bytes());
 
-        let mut mem = MainMemory { mem };
-        mem.load_fontset();
-        mem
+        Ok(mem)
+    }
+
+    pub fn size(&self) -> usize {
+        self.mem.len()
+    }
+
+    // Addresses past the end of memory wrap around to the start.
+    pub fn wrap(&self, address: usize) -> Address {
+        (address % self.mem.len()) as Address
     }
 
     pub fn read_bytes(&self, address: Address, offset: Address) -> Vec<Byte> {
-        self.mem[
-            (address as usize)..(address as usize + offset as usize)
-        ].to_vec()
+        (0..offset as usize)
+            .map(|idx| self.mem[self.wrap(address as usize + idx) as usize])
+            .collect()
+    }
+
+    // Unlike `read_bytes`, slices stop at the end of memory.
+    pub fn slice(&self, address: Address, offset: Address) -> &[Byte] {
+        let start = (address as usize).min(self.mem.len());
+        let end = (start + offset as usize).min(self.mem.len());
+        &self.mem[start..end]
     }
 
     fn load_fontset(&mut self) {
         // 0
-        &self.mem[0..5].copy_from_slice(&[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        self.mem[0..5].copy_from_slice(&[0xF0, 0x90, 0x90, 0x90, 0xF0]);
 
         // 1
-        &self.mem[5..10].copy_from_slice(&[0x20, 0x60, 0x20, 0x20, 0x70]);
+        self.mem[5..10].copy_from_slice(&[0x20, 0x60, 0x20, 0x20, 0x70]);
 
         // 2
-        &self.mem[10..15].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x80, 0xF0]);
+        self.mem[10..15].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x80, 0xF0]);
 
         // 3
-        &self.mem[15..20].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x10, 0xF0]);
+        self.mem[15..20].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x10, 0xF0]);
 
         // 4
-        &self.mem[20..25].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x10, 0xF0]);
+        self.mem[20..25].copy_from_slice(&[0xF0, 0x10, 0xF0, 0x10, 0xF0]);
 
         // 5
-        &self.mem[25..30].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x10, 0xF0]);
+        self.mem[25..30].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x10, 0xF0]);
 
         // 6
-        &self.mem[30..35].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x90, 0xF0]);
+        self.mem[30..35].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x90, 0xF0]);
 
         // 7
-        &self.mem[35..40].copy_from_slice(&[0xF0, 0x10, 0x20, 0x40, 0x40]);
+        self.mem[35..40].copy_from_slice(&[0xF0, 0x10, 0x20, 0x40, 0x40]);
 
         // 8
-        &self.mem[40..45].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x90, 0xF0]);
+        self.mem[40..45].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x90, 0xF0]);
 
         // 9
-        &self.mem[45..50].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x10, 0xF0]);
+        self.mem[45..50].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x10, 0xF0]);
 
         // A
-        &self.mem[50..55].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x90, 0x90]);
+        self.mem[50..55].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x90, 0x90]);
 
         // B
-        &self.mem[55..60].copy_from_slice(&[0xE0, 0x90, 0xE0, 0x90, 0xE0]);
+        self.mem[55..60].copy_from_slice(&[0xE0, 0x90, 0xE0, 0x90, 0xE0]);
 
         // C
-        &self.mem[60..65].copy_from_slice(&[0xF0, 0x80, 0x80, 0x80, 0xF0]);
+        self.mem[60..65].copy_from_slice(&[0xF0, 0x80, 0x80, 0x80, 0xF0]);
 
         // D
-        &self.mem[65..70].copy_from_slice(&[0xE0, 0x90, 0x90, 0x90, 0xE0]);
+        self.mem[65..70].copy_from_slice(&[0xE0, 0x90, 0x90, 0x90, 0xE0]);
 
         // E
-        &self.mem[70..75].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x80, 0xF0]);
+        self.mem[70..75].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x80, 0xF0]);
 
         // F
-        &self.mem[75..80].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x80, 0x80]);
+        self.mem[75..80].copy_from_slice(&[0xF0, 0x80, 0xF0, 0x80, 0x80]);
+
+        let big_font_end = BIG_FONT_BEGIN + BIG_FONTSET.len();
+        self.mem[BIG_FONT_BEGIN..big_font_end].copy_from_slice(&BIG_FONTSET);
     }
 }
 
@@ -99,14 +173,14 @@ impl Memory for MainMemory {
     fn read(&self, address: Self::Address) -> Self::Value {
         let real_address = address as usize;
 
-        assert!(real_address < MEMORY_SIZE, "Address out of memory space.");
+        assert!(real_address < self.mem.len(), "Address out of memory space.");
         self.mem[real_address]
     }
 
     fn write(&mut self, address: Self::Address, value: Self::Value) {
         let real_address = address as usize;
 
-        assert!(real_address < MEMORY_SIZE, "Address out of memory space.");
+        assert!(real_address < self.mem.len(), "Address out of memory space.");
         self.mem[real_address] = value;
     }
 }
@@ -148,19 +222,23 @@ impl ROM {
     }
 }
 
+impl From<Vec<Byte>> for ROM {
+    fn from(data: Vec<Byte>) -> Self {
+        ROM { data }
+    }
+}
+
 impl fmt::Display for MainMemory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use termion::{color, style};
 
-        let bytes = self.read_bytes(0x000, 0x1000);
-
         write!(f, "       ")?;
         for i in 0..16 {
             write!(f, "{:<4X} ", i * 2)?;
         }
-        write!(f, "\n")?;
+        writeln!(f)?;
 
-        for (idx, byte_pack) in bytes.chunks(32).enumerate() {
+        for (idx, byte_pack) in self.mem.chunks(32).enumerate() {
             write!(f, "{:#05X}  ", idx * 32)?;
             for byte in byte_pack.chunks(2) {
                 if byte[0] == 0x0 && byte[1] == 0x0 {
@@ -170,7 +248,7 @@ impl fmt::Display for MainMemory {
                 write!(f, "{:02x} ", byte[1])?;
                 write!(f, "{}", style::Reset)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
 
         Ok(())
@@ -180,6 +258,7 @@ impl fmt::Display for MainMemory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::specs::MEMORY_SIZE;
 
     #[test]
     fn test_read() {
@@ -195,6 +274,34 @@ mod tests {
         assert_eq!(mem.mem[0x345], 23);
     }
 
+    #[test]
+    fn test_extended_memory() {
+        let mut mem = MainMemory::with_rom(&ROM::from(vec![0x12, 0x00]), Platform::XoChip).unwrap();
+        mem.write(0xFFFF, 42);
+        assert_eq!(mem.read(0xFFFF), 42);
+        assert_eq!(mem.read(0x200), 0x12);
+    }
+
+    #[test]
+    fn test_rom_too_large() {
+        let rom = ROM::from(vec![0x0; MEMORY_SIZE - PROGRAM_BEGIN + 1]);
+        assert!(matches!(MainMemory::with_rom(&rom, Platform::Chip8),
+                         Err(MemoryError::RomTooLarge(3585, 3584))));
+        assert!(MainMemory::with_rom(&rom, Platform::XoChip).is_ok());
+    }
+
+    #[test]
+    fn test_accesses_past_the_end() {
+        let mut mem = MainMemory::new();
+        mem.write(0xFFF, 0x1);
+        mem.write(0x000, 0x2);
+
+        assert_eq!(mem.read_bytes(0xFFF, 3), vec![0x1, 0x2, 0x90]);
+        assert_eq!(mem.slice(0xFFF, 3), &[0x1]);
+        assert!(mem.slice(0xFFFF, 15).is_empty());
+        assert_eq!(mem.wrap(0x1001), 0x001);
+    }
+
     #[test]
     #[should_panic]
     fn test_write_out_of_bound() {
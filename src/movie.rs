@@ -0,0 +1,271 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::input::KeypadState;
+use crate::specs::Platform;
+use crate::display::SpriteEdge;
+
+static MAGIC: &str = "CHIP8-MOVIE 1";
+
+pub mod error {
+    use std::fmt;
+    use std::io;
+
+    #[derive(Debug)]
+    pub enum MovieError {
+        IOError(io::Error),
+        ParseError(usize, String),
+    }
+
+    impl From<io::Error> for MovieError {
+        fn from(err: io::Error) -> Self {
+            MovieError::IOError(err)
+        }
+    }
+
+    impl fmt::Display for MovieError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                MovieError::IOError(err) => write!(f, "I/O error: {}", err),
+                MovieError::ParseError(line, msg) => {
+                    write!(f, "movie line {}: {}", line, msg)
+                },
+            }
+        }
+    }
+}
+
+use error::MovieError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub keys: KeypadState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub seed: u64,
+    pub instructions_per_frame: usize,
+    pub platform: Platform,
+    pub sprite_edge: SpriteEdge,
+    pub length: u64,
+    events: Vec<InputEvent>,
+    resets: Vec<u64>,
+}
+
+impl Movie {
+    pub fn new(seed: u64, instructions_per_frame: usize, platform: Platform) -> Self {
+        Movie {
+            seed,
+            instructions_per_frame,
+            platform,
+            sprite_edge: SpriteEdge::Clip,
+            length: 0,
+            events: Vec::new(),
+            resets: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, keys: KeypadState) {
+        let last_keys = self.events.last().map_or(0x0, |event| event.keys);
+
+        if keys != last_keys {
+            self.events.push(InputEvent { frame, keys });
+        }
+    }
+
+    pub fn record_reset(&mut self, frame: u64) {
+        if self.resets.last() != Some(&frame) {
+            self.resets.push(frame);
+        }
+    }
+
+    pub fn has_reset(&self, frame: u64) -> bool {
+        self.resets.binary_search(&frame).is_ok()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut lines = text.lines().enumerate()
+                            .map(|(idx, line)| (idx + 1, line.trim()))
+                            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, line)) if line == MAGIC => {},
+            Some((idx, _)) => {
+                return Err(MovieError::ParseError(idx, "not a movie file".to_owned()))
+            },
+            None => return Err(MovieError::ParseError(0, "empty movie file".to_owned())),
+        }
+
+        // Movies recorded before platforms existed are plain CHIP-8.
+        let mut movie = Movie::new(0, 0, Platform::Chip8);
+
+        for (idx, line) in lines {
+            let mut tokens = line.split_whitespace();
+            let (key, value) = match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some(key), Some(value), None) => (key, value),
+                _ => return Err(MovieError::ParseError(idx, "expected two fields".to_owned())),
+            };
+            let bad_value = || MovieError::ParseError(idx, format!("invalid value: {}", value));
+
+            match key {
+                "seed" => movie.seed = value.parse().map_err(|_| bad_value())?,
+                "ipf" => movie.instructions_per_frame = value.parse().map_err(|_| bad_value())?,
+                "platform" => movie.platform = value.parse().map_err(|_| bad_value())?,
+                "edge" => movie.sprite_edge = value.parse().map_err(|_| bad_value())?,
+                "length" => movie.length = value.parse().map_err(|_| bad_value())?,
+                "reset" => {
+                    let frame = value.parse().map_err(|_| bad_value())?;
+                    if movie.resets.last().is_some_and(|&last| last > frame) {
+                        return Err(MovieError::ParseError(idx, "resets out of order".to_owned()));
+                    }
+                    movie.resets.push(frame);
+                },
+                frame => {
+                    let frame = frame.parse().map_err(|_| {
+                        MovieError::ParseError(idx, format!("invalid frame: {}", frame))
+                    })?;
+                    let keys = KeypadState::from_str_radix(value, 16).map_err(|_| bad_value())?;
+
+                    if movie.events.last().is_some_and(|event| event.frame > frame) {
+                        return Err(MovieError::ParseError(idx, "frames out of order".to_owned()));
+                    }
+                    movie.events.push(InputEvent { frame, keys });
+                },
+            }
+        }
+
+        if movie.instructions_per_frame == 0 {
+            return Err(MovieError::ParseError(0, "missing instructions per frame".to_owned()));
+        }
+
+        Ok(movie)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, MovieError> {
+        Movie::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", MAGIC)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "ipf {}", self.instructions_per_frame)?;
+        writeln!(f, "platform {}", self.platform)?;
+        writeln!(f, "edge {}", self.sprite_edge)?;
+        writeln!(f, "length {}", self.length)?;
+
+        for frame in &self.resets {
+            writeln!(f, "reset {}", frame)?;
+        }
+
+        for event in &self.events {
+            writeln!(f, "{} {:04X}", event.frame, event.keys)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Player {
+    movie: Movie,
+    cursor: usize,
+    keys: KeypadState,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Player {
+            movie,
+            cursor: 0,
+            keys: 0x0,
+        }
+    }
+
+    pub fn get_movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn rewind(&mut self) {
+        self.cursor = 0;
+        self.keys = 0x0;
+    }
+
+    pub fn is_finished(&self, frame: u64) -> bool {
+        frame >= self.movie.length
+    }
+
+    pub fn keys_at(&mut self, frame: u64) -> KeypadState {
+        while let Some(event) = self.movie.events.get(self.cursor) {
+            if event.frame > frame {
+                break;
+            }
+            self.keys = event.keys;
+            self.cursor += 1;
+        }
+
+        self.keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_only_changes() {
+        let mut movie = Movie::new(42, 8, Platform::Chip8);
+        movie.record(0, 0x0);
+        movie.record(3, 0x10);
+        movie.record(4, 0x10);
+        movie.record(9, 0x0);
+
+        assert_eq!(movie.events, vec![
+            InputEvent { frame: 3, keys: 0x10 },
+            InputEvent { frame: 9, keys: 0x0 },
+        ]);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut movie = Movie::new(0xDEADBEEF, 10, Platform::XoChip);
+        movie.sprite_edge = SpriteEdge::Wrap;
+        movie.record(12, 0x8001);
+        movie.record(40, 0x0);
+        movie.record_reset(25);
+        movie.length = 60;
+
+        assert_eq!(Movie::parse(&movie.to_string()).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Movie::parse("").is_err());
+        assert!(Movie::parse("hello").is_err());
+        assert!(Movie::parse("CHIP8-MOVIE 1\nseed 1\nipf 8\n10 0001\n5 0000").is_err());
+        assert!(Movie::parse("CHIP8-MOVIE 1\nseed 1").is_err());
+        assert!(Movie::parse("CHIP8-MOVIE 1\nseed 1\nipf 8\nplatform nes").is_err());
+        assert!(Movie::parse("CHIP8-MOVIE 1\nseed 1\nipf 8\nreset 9\nreset 2").is_err());
+    }
+
+    #[test]
+    fn test_player() {
+        let mut movie = Movie::new(0, 8, Platform::Chip8);
+        movie.record(2, 0x1);
+        movie.record(5, 0x3);
+
+        let mut player = Player::new(movie);
+        assert_eq!(player.keys_at(0), 0x0);
+        assert_eq!(player.keys_at(2), 0x1);
+        assert_eq!(player.keys_at(4), 0x1);
+        assert_eq!(player.keys_at(5), 0x3);
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+pub type Color = u32;
+
+pub const PALETTE_NAMES: [&str; 4] = ["classic", "amber", "green", "octo"];
+
+// Keys of the colours in a palette file, in palette order.
+const COLOR_KEYS: [&str; 4] = ["background", "plane1", "plane2", "both"];
+
+pub mod error {
+    use std::fmt;
+    use std::io;
+
+    #[derive(Debug)]
+    pub enum PaletteError {
+        IOError(io::Error),
+        ParseError(usize, String),
+    }
+
+    impl From<io::Error> for PaletteError {
+        fn from(err: io::Error) -> Self {
+            PaletteError::IOError(err)
+        }
+    }
+
+    impl fmt::Display for PaletteError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                PaletteError::IOError(err) => write!(f, "I/O error: {}", err),
+                PaletteError::ParseError(line, msg) => {
+                    write!(f, "palette file line {}: {}", line, msg)
+                },
+            }
+        }
+    }
+}
+
+use error::PaletteError;
+
+// Colours are indexed by the planes a pixel is lit on: background, first
+// plane, second plane and both planes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    colors: [Color; 4],
+}
+
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.trim().trim_start_matches('#');
+    match Color::from_str_radix(hex, 16) {
+        Ok(value) if hex.len() == 6 => Some(value),
+        _ => None,
+    }
+}
+
+pub fn mix(from: Color, to: Color, amount: f32) -> Color {
+    (0..3).rev().fold(0, |color, channel| {
+        let from = (from >> (channel * 8) & 0xFF) as f32;
+        let to = (to >> (channel * 8) & 0xFF) as f32;
+        (color << 8) | (from + (to - from) * amount).round() as Color
+    })
+}
+
+impl Palette {
+    pub fn new(colors: [Color; 4]) -> Self {
+        Palette { colors }
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        let colors = match name {
+            "classic" => [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            "amber" => [0x1A1000, 0xFFB000, 0xB37B00, 0x664600],
+            "green" => [0x001A00, 0x33FF33, 0x1FA61F, 0x0F590F],
+            "octo" => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+            _ => return None,
+        };
+
+        Some(Palette::new(colors))
+    }
+
+    // Palette files give `<key> <hex colour>` pairs, one per line, with the
+    // keys `background`, `plane1`, `plane2` and `both`. Lines starting with
+    // `#` are comments and missing colours come from the classic palette.
+    pub fn parse(text: &str) -> Result<Self, PaletteError> {
+        let mut palette = Palette::default();
+
+        let lines = text.lines().enumerate()
+                        .map(|(idx, line)| (idx + 1, line.trim()))
+                        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        for (idx, line) in lines {
+            let mut tokens = line.split_whitespace();
+            let (key, color) = match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some(key), Some(color), None) => (key, color),
+                _ => return Err(PaletteError::ParseError(idx, "expected a key and a colour".to_owned())),
+            };
+            let index = COLOR_KEYS.iter().position(|name| *name == key)
+                .ok_or_else(|| PaletteError::ParseError(idx, format!("unknown key: {}", key)))?;
+
+            palette.colors[index] = parse_color(color)
+                .ok_or_else(|| PaletteError::ParseError(idx, format!("invalid colour: {}", color)))?;
+        }
+
+        Ok(palette)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PaletteError> {
+        Palette::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn color(&self, index: usize) -> Color {
+        self.colors[index % self.colors.len()]
+    }
+
+    pub fn grid_color(&self) -> Color {
+        mix(self.colors[0], self.colors[1], 0.2)
+    }
+
+    pub fn border_color(&self) -> Color {
+        mix(self.colors[0], self.colors[1], 0.5)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::named("classic").unwrap()
+    }
+}
+
+// Accepts a palette name or up to four comma separated hex colours, missing
+// colours are taken from the classic palette.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = Palette::named(s) {
+            return Ok(palette);
+        }
+
+        let mut palette = Palette::default();
+        let colors: Vec<&str> = s.split(',').collect();
+        if colors.len() > palette.colors.len() {
+            return Err(format!("Too many colours in palette: {}", s));
+        }
+
+        for (idx, color) in colors.into_iter().enumerate() {
+            palette.colors[idx] = parse_color(color).ok_or_else(|| {
+                format!("Invalid colour or palette: {} (palettes: {})", color, PALETTE_NAMES.join(", "))
+            })?;
+        }
+
+        Ok(palette)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("amber".parse(), Ok(Palette::named("amber").unwrap()));
+        assert!(PALETTE_NAMES.iter().all(|name| name.parse::<Palette>().is_ok()));
+        assert_eq!("#102030,ffffff".parse::<Palette>().map(|palette| palette.color(0)), Ok(0x102030));
+        assert_eq!("102030".parse::<Palette>().map(|palette| palette.color(3)), Ok(0x555555));
+        assert!("ugly".parse::<Palette>().is_err());
+        assert!("1,2,3,4,5".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let palette = Palette::parse("# Amber monitor\nbackground 1a1000\n\nboth #664600\n").unwrap();
+        assert_eq!(palette.color(0), 0x1A1000);
+        assert_eq!(palette.color(1), 0xFFFFFF);
+        assert_eq!(palette.color(3), 0x664600);
+
+        assert!(Palette::parse("plane3 ffffff").is_err());
+        assert!(Palette::parse("plane1 fff").is_err());
+        assert!(Palette::parse("plane1").is_err());
+    }
+
+    #[test]
+    fn test_mix() {
+        assert_eq!(mix(0x000000, 0xFFFFFF, 0.5), 0x808080);
+        assert_eq!(mix(0x102030, 0x102030, 0.7), 0x102030);
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::display::FrameBuffer;
+use crate::palette::Palette;
+use crate::specs::FRAME_RATE;
+
+// Every frame is drawn on a canvas the size of the hires screen, lores frames
+// are scaled up twice as much.
+const CANVAS_WIDTH: usize = 128;
+const CANVAS_HEIGHT: usize = 64;
+const PALETTE_SIZE: usize = 4;
+
+fn encoding_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+// GIF delays are in hundredths of a second, rounding each frame delay on its
+// own would make long recordings drift.
+fn centiseconds(frames: u64) -> u64 {
+    (frames * 100 + FRAME_RATE as u64 / 2) / FRAME_RATE as u64
+}
+
+pub struct GifRecorder<W: Write> {
+    encoder: Option<gif::Encoder<W>>,
+    scale: usize,
+    interval: u64,
+    frame: u64,
+    pending: Option<(Vec<u8>, u64)>,
+    error: Option<io::Error>,
+}
+
+impl GifRecorder<BufWriter<File>> {
+    pub fn create(path: &Path, palette: &Palette, scale: usize, interval: u64) -> io::Result<Self> {
+        GifRecorder::new(BufWriter::new(File::create(path)?), palette, scale, interval)
+    }
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(output: W, palette: &Palette, scale: usize, interval: u64) -> io::Result<Self> {
+        let scale = scale.max(1);
+        let colors: Vec<u8> = (0..PALETTE_SIZE)
+            .flat_map(|index| {
+                let color = palette.color(index);
+                [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+            })
+            .collect();
+
+        let mut encoder = gif::Encoder::new(output,
+                                            (CANVAS_WIDTH * scale) as u16,
+                                            (CANVAS_HEIGHT * scale) as u16,
+                                            &colors).map_err(encoding_error)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(encoding_error)?;
+
+        Ok(GifRecorder {
+            encoder: Some(encoder),
+            scale,
+            interval: interval.max(1),
+            frame: 0,
+            pending: None,
+            error: None,
+        })
+    }
+
+    fn render(&self, frame_buffer: &FrameBuffer) -> Vec<u8> {
+        let scale = self.scale * CANVAS_WIDTH / frame_buffer.width();
+        let (width, height) = (CANVAS_WIDTH * self.scale, CANVAS_HEIGHT * self.scale);
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(frame_buffer.pixel((x / scale, y / scale)) as u8);
+            }
+        }
+
+        pixels
+    }
+
+    fn write_pending(&mut self, end: u64) -> io::Result<()> {
+        let (pixels, start) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let encoder = match self.encoder {
+            Some(ref mut encoder) => encoder,
+            None => return Ok(()),
+        };
+
+        let mut frame = gif::Frame::from_indexed_pixels((CANVAS_WIDTH * self.scale) as u16,
+                                                        (CANVAS_HEIGHT * self.scale) as u16,
+                                                        pixels, None);
+        frame.delay = (centiseconds(end) - centiseconds(start)) as u16;
+        encoder.write_frame(&frame).map_err(encoding_error)
+    }
+
+    fn write_frame(&mut self, frame: u64, frame_buffer: &FrameBuffer) -> io::Result<()> {
+        // Identical frames are merged into a longer one.
+        let pixels = self.render(frame_buffer);
+        if self.pending.as_ref().is_some_and(|(pending, _)| *pending == pixels) {
+            return Ok(());
+        }
+
+        self.write_pending(frame)?;
+        self.pending = Some((pixels, frame));
+        Ok(())
+    }
+
+    // Called once per emulated frame. Errors are kept until `finish` so the
+    // emulation loop does not have to deal with them.
+    pub fn capture(&mut self, frame_buffer: &FrameBuffer) {
+        let frame = self.frame;
+        self.frame += 1;
+
+        if !frame.is_multiple_of(self.interval) || self.error.is_some() {
+            return;
+        }
+        if let Err(err) = self.write_frame(frame, frame_buffer) {
+            self.error = Some(err);
+        }
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.write_pending(self.frame)?;
+
+        match self.encoder.take() {
+            Some(encoder) => encoder.into_inner()?.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_centiseconds() {
+        assert_eq!(centiseconds(1), 2);
+        assert_eq!(centiseconds(3), 5);
+        assert_eq!(centiseconds(60), 100);
+        assert_eq!((0..60).map(|frame| centiseconds(frame + 1) - centiseconds(frame)).sum::<u64>(), 100);
+    }
+
+    #[test]
+    fn test_gif_frames() {
+        let mut output = Vec::new();
+        let mut buff = FrameBuffer::new();
+        {
+            let mut recorder = GifRecorder::new(&mut output, &Palette::default(), 1, 2).unwrap();
+            for frame in 0..12 {
+                if frame == 6 {
+                    buff.write((0, 0), true);
+                }
+                recorder.capture(&buff);
+            }
+            recorder.finish().unwrap();
+        }
+
+        let mut decoder = gif::DecodeOptions::new();
+        decoder.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = decoder.read_info(&output[..]).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (128, 64));
+
+        let first = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(first.delay, 10);
+        assert_eq!(first.buffer[0], 0);
+
+        let second = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(second.delay, 10);
+        assert_eq!(&second.buffer[..3], &[1, 1, 0]);
+        assert!(decoder.read_next_frame().unwrap().is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::specs::FRAME_RATE;
+use crate::machine::Machine;
+
+const MAX_LAG_FRAMES: u32 = 5;
+
+pub struct Scheduler {
+    frame_duration: Option<Duration>,
+    deadline: Instant,
+}
+
+impl Scheduler {
+    pub fn new(frame_rate: u32) -> Self {
+        Scheduler {
+            frame_duration: Some(Duration::from_secs(1) / frame_rate),
+            deadline: Instant::now(),
+        }
+    }
+
+    pub fn realtime() -> Self {
+        Scheduler::new(FRAME_RATE)
+    }
+
+    pub fn unthrottled() -> Self {
+        Scheduler {
+            frame_duration: None,
+            deadline: Instant::now(),
+        }
+    }
+
+    pub fn wait(&mut self) {
+        let frame_duration = match self.frame_duration {
+            Some(duration) => duration,
+            None => return,
+        };
+
+        self.deadline += frame_duration;
+
+        let now = Instant::now();
+        if self.deadline > now {
+            thread::sleep(self.deadline - now);
+        } else if now - self.deadline > frame_duration * MAX_LAG_FRAMES {
+            // The host could not keep up: drop the backlog instead of running
+            // a burst of frames to catch up.
+            self.deadline = now;
+        }
+    }
+
+    pub fn run_frame(&mut self, machine: &mut Machine) {
+        self.run_frames(machine, 1);
+    }
+
+    pub fn run_frames(&mut self, machine: &mut Machine, count: usize) {
+        for _ in 0..count {
+            machine.run_frame();
+        }
+        self.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::ROM;
+    use crate::watcher::Watcher;
+    use crate::specs::Platform;
+
+    fn idle_machine() -> Machine {
+        let rom = ROM::from(vec![0x12, 0x00]);
+        Machine::new(rom, Watcher::new(), 0, Platform::Chip8).unwrap()
+    }
+
+    #[test]
+    fn test_paced_frames() {
+        let mut machine = idle_machine();
+        let mut scheduler = Scheduler::new(1000);
+        let start = Instant::now();
+
+        for _ in 0..20 {
+            scheduler.run_frame(&mut machine);
+        }
+
+        assert_eq!(machine.get_frame(), 20);
+        assert!(Instant::now() - start >= Duration::from_millis(19));
+    }
+
+    #[test]
+    fn test_unthrottled_frames() {
+        let mut machine = idle_machine();
+        let mut scheduler = Scheduler::unthrottled();
+
+        for _ in 0..1000 {
+            scheduler.run_frame(&mut machine);
+        }
+
+        assert_eq!(machine.get_frame(), 1000);
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::display::FrameBuffer;
+use crate::palette::{Color, Palette};
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn render(frame_buffer: &FrameBuffer, palette: &Palette, scale: usize) -> Self {
+        let scale = scale.max(1);
+        let (width, height) = (frame_buffer.width() * scale, frame_buffer.height() * scale);
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(palette.color(frame_buffer.pixel((x / scale, y / scale))));
+            }
+        }
+
+        Image { width, height, pixels }
+    }
+
+    pub fn rgb(&self) -> Vec<u8> {
+        self.pixels.iter()
+                   .flat_map(|color| [(color >> 16) as u8, (color >> 8) as u8, *color as u8])
+                   .collect()
+    }
+
+    pub fn write_ppm<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        write!(output, "P6\n{} {}\n255\n", self.width, self.height)?;
+        output.write_all(&self.rgb())
+    }
+
+    pub fn write_png<W: Write>(&self, output: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(output, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&self.rgb()).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if extension != Some("png") && extension != Some("ppm") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "screenshots must be .png or .ppm files"));
+        }
+
+        let mut output = BufWriter::new(File::create(path)?);
+        if extension == Some("png") {
+            self.write_png(output)
+        } else {
+            self.write_ppm(&mut output)?;
+            output.flush()
+        }
+    }
+}
+
+pub struct Screenshot {
+    pub palette: Palette,
+    pub scale: usize,
+}
+
+impl Screenshot {
+    pub fn new(palette: Palette, scale: usize) -> Self {
+        Screenshot { palette, scale }
+    }
+
+    pub fn save(&self, frame_buffer: &FrameBuffer, path: &Path) -> io::Result<()> {
+        Image::render(frame_buffer, &self.palette, self.scale).save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_render_scaled() {
+        let mut buff = FrameBuffer::new();
+        buff.write((1, 0), true);
+
+        let image = Image::render(&buff, &Palette::default(), 2);
+        assert_eq!((image.width, image.height), (128, 64));
+        assert_eq!(image.pixels[1], 0x000000);
+        assert_eq!(image.pixels[2], 0xFFFFFF);
+        assert_eq!(image.pixels[128 + 3], 0xFFFFFF);
+        assert_eq!(image.pixels[128 * 2 + 3], 0x000000);
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut buff = FrameBuffer::new();
+        buff.write((0, 0), true);
+
+        let mut output = Vec::new();
+        Image::render(&buff, &Palette::default(), 1).write_ppm(&mut output).unwrap();
+
+        let header = b"P6\n64 32\n255\n";
+        assert_eq!(&output[..header.len()], header);
+        assert_eq!(output.len(), header.len() + 64 * 32 * 3);
+        assert_eq!(&output[header.len()..header.len() + 6], &[0xFF, 0xFF, 0xFF, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_png() {
+        let mut output = Vec::new();
+        Image::render(&FrameBuffer::new(), &Palette::default(), 1).write_png(&mut output).unwrap();
+
+        assert_eq!(&output[..8], b"\x89PNG\r\n\x1a\n");
+    }
+}
@@ -1,10 +1,60 @@
+use std::fmt;
+use std::str::FromStr;
+
 pub const MEMORY_SIZE: usize = 4096;
+pub const XO_MEMORY_SIZE: usize = 0x10000;
 pub const STACK_SIZE: usize = 16;
 pub const REGISTERS_COUNT: usize = 16;
 pub const PROGRAM_BEGIN: usize = 0x200;
+pub const BIG_FONT_BEGIN: usize = 0x50;
+pub const FLAGS_COUNT: usize = 16;
+pub const FRAME_RATE: u32 = 60;
+pub const PLANES_COUNT: usize = 2;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+pub const DEFAULT_PITCH: u8 = 64;
 
 pub type Nibble = u8;
 pub type Byte = u8;
 pub type Instruction = u16;
 pub type Address = u16;
 pub type Register<T> = T;
+
+// Ordered so that each platform runs everything the previous ones do.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip => XO_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!("Unknown platform: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "chip8"),
+            Platform::SuperChip => write!(f, "schip"),
+            Platform::XoChip => write!(f, "xochip"),
+        }
+    }
+}
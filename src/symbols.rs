@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::fs;
+use std::path::Path;
+
+use crate::specs::Address;
+
+pub mod error {
+    use std::fmt;
+    use std::io;
+
+    #[derive(Debug)]
+    pub enum SymbolError {
+        IOError(io::Error),
+        ParseError(usize, String),
+    }
+
+    impl From<io::Error> for SymbolError {
+        fn from(err: io::Error) -> Self {
+            SymbolError::IOError(err)
+        }
+    }
+
+    impl fmt::Display for SymbolError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                SymbolError::IOError(err) => write!(f, "I/O error: {}", err),
+                SymbolError::ParseError(line, msg) => {
+                    write!(f, "symbol file line {}: {}", line, msg)
+                },
+            }
+        }
+    }
+}
+
+use error::SymbolError;
+
+// Symbol files list one `<hex address> <name>` pair per line, `#` starts a
+// comment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    symbols: BTreeMap<Address, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut table = SymbolTable::new();
+
+        let lines = text.lines().enumerate()
+                        .map(|(idx, line)| (idx + 1, line.trim()))
+                        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        for (idx, line) in lines {
+            let mut tokens = line.split_whitespace();
+            let (address, name) = match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some(address), Some(name), None) => (address, name),
+                _ => return Err(SymbolError::ParseError(idx, "expected an address and a name".to_owned())),
+            };
+            let address = Address::from_str_radix(address.trim_start_matches("0x"), 16)
+                .map_err(|_| SymbolError::ParseError(idx, format!("invalid address: {}", address)))?;
+
+            table.insert(address, name);
+        }
+
+        Ok(table)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SymbolError> {
+        SymbolTable::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn insert(&mut self, address: Address, name: &str) {
+        self.symbols.insert(address, name.to_owned());
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<Address> {
+        self.symbols.iter().find(|(_, symbol)| *symbol == name).map(|(address, _)| *address)
+    }
+
+    // Returns the address of the first symbol after the given one.
+    pub fn next_symbol(&self, address: Address) -> Option<Address> {
+        self.symbols.range((Bound::Excluded(address), Bound::Unbounded)).next()
+            .map(|(address, _)| *address)
+    }
+
+    // Returns the closest symbol at or before the address, and the offset
+    // from it.
+    pub fn lookup(&self, address: Address) -> Option<(&str, Address)> {
+        self.symbols.range(..=address).next_back()
+            .map(|(start, name)| (name.as_str(), address - start))
+    }
+
+    pub fn describe(&self, address: Address) -> Option<String> {
+        self.lookup(address).map(|(name, offset)| match offset {
+            0 => name.to_owned(),
+            offset => format!("{}+{:#X}", name, offset),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let table = SymbolTable::parse("# game\n0x200 main\n\n2A0 draw_player\n").unwrap();
+
+        assert_eq!(table.lookup(0x1FE), None);
+        assert_eq!(table.lookup(0x200), Some(("main", 0)));
+        assert_eq!(table.lookup(0x29E), Some(("main", 0x9E)));
+        assert_eq!(table.describe(0x2A0), Some("draw_player".to_owned()));
+        assert_eq!(table.describe(0x2A4), Some("draw_player+0x4".to_owned()));
+        assert_eq!(table.address_of("draw_player"), Some(0x2A0));
+        assert_eq!(table.address_of("missing"), None);
+        assert_eq!(table.next_symbol(0x200), Some(0x2A0));
+        assert_eq!(table.next_symbol(0x2A0), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(SymbolTable::parse("0x200"), Err(SymbolError::ParseError(1, _))));
+        assert!(matches!(SymbolTable::parse("0x200 main\nmain 0x200"), Err(SymbolError::ParseError(2, _))));
+        assert!(matches!(SymbolTable::parse("0x200 main extra"), Err(SymbolError::ParseError(1, _))));
+    }
+}
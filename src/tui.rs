@@ -0,0 +1,175 @@
+extern crate termion;
+
+use std::io;
+use std::io::{Stdout, Write};
+
+use termion::{async_stdin, clear, cursor};
+use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::AlternateScreen;
+use termion::AsyncReader;
+
+use crate::display::FrameBuffer;
+use crate::frontend::{Command, Controls, Frontend, Status};
+use crate::memory::Memory;
+
+// Terminals only report key presses, a key is considered held for this many
+// frames after its last press or auto-repeat.
+const KEY_HOLD_FRAMES: u8 = 8;
+
+pub struct Terminal {
+    screen: AlternateScreen<RawTerminal<Stdout>>,
+    input: Keys<AsyncReader>,
+    held: [u8; 16],
+    fast_forward: u8,
+    rows: Vec<String>,
+    status: String,
+}
+
+impl Terminal {
+    pub fn new() -> io::Result<Self> {
+        let mut screen = AlternateScreen::from(io::stdout().into_raw_mode()?);
+        write!(screen, "{}{}", clear::All, cursor::Hide)?;
+        screen.flush()?;
+
+        Ok(Terminal {
+            screen,
+            input: async_stdin().keys(),
+            held: [0; 16],
+            fast_forward: 0,
+            rows: Vec::new(),
+            status: String::new(),
+        })
+    }
+
+    fn key_mapping(chr: char) -> Option<u8> {
+        chr.to_digit(16).map(|digit| digit as u8)
+    }
+
+    // Draws two rows of pixels per character. A shrunk screen lights a
+    // pixel when any of the pixels it covers is lit.
+    pub fn render_row(frame_buffer: &FrameBuffer, row: usize, scale: usize) -> String {
+        let (width, height) = (frame_buffer.width(), frame_buffer.height());
+        let lit = |x: usize, y: usize| {
+            (y * scale..((y + 1) * scale).min(height)).any(|y| {
+                (x * scale..((x + 1) * scale).min(width)).any(|x| frame_buffer.read((x, y)))
+            })
+        };
+
+        (0..width.div_ceil(scale)).map(|x| {
+            match (lit(x, row * 2), lit(x, row * 2 + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            }
+        }).collect()
+    }
+
+    fn draw_screen(&mut self, frame_buffer: &FrameBuffer, status: &Status) -> io::Result<()> {
+        let height = frame_buffer.height().div_ceil(2);
+
+        if self.rows.len() != height {
+            self.rows = vec![String::new(); height];
+            write!(self.screen, "{}", clear::All)?;
+        }
+
+        for row in 0..height {
+            let line = Terminal::render_row(frame_buffer, row, 1);
+
+            if line != self.rows[row] {
+                write!(self.screen, "{}{}", cursor::Goto(1, row as u16 + 1), line)?;
+                self.rows[row] = line;
+            }
+        }
+
+        let status = status.to_string();
+        if status != self.status {
+            write!(self.screen, "{}{}{}", cursor::Goto(1, height as u16 + 2),
+                   clear::CurrentLine, status)?;
+            self.status = status;
+        }
+
+        self.screen.flush()
+    }
+}
+
+impl Frontend for Terminal {
+    fn poll(&mut self) -> Controls {
+        let mut controls = Controls::default();
+
+        for counter in self.held.iter_mut().chain(Some(&mut self.fast_forward)) {
+            *counter = counter.saturating_sub(1);
+        }
+
+        while let Some(Ok(key)) = self.input.next() {
+            match key {
+                Key::Char('p') => controls.commands.push(Command::TogglePause),
+                Key::Char('n') => controls.commands.push(Command::Advance),
+                Key::Char('r') => controls.commands.push(Command::Reset),
+                Key::Char('+') | Key::Char('=') => controls.commands.push(Command::Faster),
+                Key::Char('-') => controls.commands.push(Command::Slower),
+                Key::Char('s') => controls.commands.push(Command::Screenshot),
+                Key::Char('\t') => self.fast_forward = KEY_HOLD_FRAMES,
+                Key::Char('q') | Key::Esc | Key::Ctrl('c') => controls.quit = true,
+                Key::Char(chr) => {
+                    if let Some(key) = Terminal::key_mapping(chr) {
+                        self.held[key as usize] = KEY_HOLD_FRAMES;
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        controls.keys = self.held.iter().enumerate()
+                                 .filter(|(_, counter)| **counter > 0)
+                                 .fold(0x0, |state, (key, _)| state | (1 << key));
+        controls.fast_forward = self.fast_forward > 0;
+        controls
+    }
+
+    fn present(&mut self, frame_buffer: &FrameBuffer, status: &Status) {
+        self.draw_screen(frame_buffer, status).unwrap();
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = write!(self.screen, "{}", cursor::Show);
+        let _ = self.screen.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_blocks() {
+        let mut buff = FrameBuffer::new();
+        buff.write((0, 0), true);
+        buff.write((1, 1), true);
+        buff.write((2, 0), true);
+        buff.write((2, 1), true);
+
+        let row = Terminal::render_row(&buff, 0, 1);
+        assert_eq!(row.chars().count(), 64);
+        assert!(row.starts_with("▀▄█ "));
+        assert!(Terminal::render_row(&buff, 1, 1).trim().is_empty());
+
+        buff.set_hires(true);
+        buff.write((127, 3), true);
+        let row = Terminal::render_row(&buff, 0, 2);
+        assert_eq!(row.chars().count(), 64);
+        assert!(row.ends_with('▄'));
+    }
+
+    #[test]
+    fn test_key_mapping() {
+        assert_eq!(Terminal::key_mapping('7'), Some(0x7));
+        assert_eq!(Terminal::key_mapping('c'), Some(0xC));
+        assert_eq!(Terminal::key_mapping('F'), Some(0xF));
+        assert_eq!(Terminal::key_mapping('x'), None);
+    }
+}
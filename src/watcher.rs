@@ -1,40 +1,77 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use crate::asm::InstructionData;
 use crate::specs::{Address, Byte, Nibble};
 
+// Clones share the same log, so the CPU and the bus can both report to the
+// debugger.
+#[derive(Clone)]
 pub struct Watcher {
     pub verbose: bool,
-    pub debug: bool,
+    log: Rc<RefCell<Option<Vec<Message>>>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Message {
     Execute { instr: InstructionData, new_pc: Address },
     RegisterChange { id: Nibble, old: Byte, new: Byte },
+    MemoryRead { address: Address, value: Byte },
+    MemoryWrite { address: Address, old: Byte, new: Byte },
 }
 
 impl Watcher {
     pub fn new() -> Self {
         Watcher {
             verbose: false,
-            debug: false,
+            log: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    pub fn set_logging(&self, enabled: bool) {
+        *self.log.borrow_mut() = if enabled {
+            Some(Vec::new())
+        } else {
+            None
+        };
+    }
+
+    // Whether sent messages go anywhere.
+    pub fn is_active(&self) -> bool {
+        self.verbose || self.log.borrow().is_some()
+    }
+
+    pub fn take_log(&self) -> Vec<Message> {
+        match *self.log.borrow_mut() {
+            Some(ref mut log) => log.split_off(0),
+            None => Vec::new(),
         }
     }
 
     pub fn send(&self, msg: Message) {
         use Message::*;
 
-        match msg {
-            Execute { instr, new_pc } => {
-                if self.verbose {
+        if self.verbose {
+            match msg {
+                Execute { ref instr, new_pc } => {
                     println!("Executed: {}. PC is now at: {:#05X}", instr, new_pc);
-                }
-            },
-            RegisterChange { id, old, new } => {
-                if self.verbose {
+                },
+                RegisterChange { id, old, new } => {
                     println!("Register V{:01X} changed: {:#04X} => {:#04X}",
                              id, old, new);
-                }
-            },
+                },
+                MemoryRead { address, value } => {
+                    println!("Memory read at {:#05X}: {:#04X}", address, value);
+                },
+                MemoryWrite { address, old, new } => {
+                    println!("Memory at {:#05X} changed: {:#04X} => {:#04X}",
+                             address, old, new);
+                },
+            }
+        }
+
+        if let Some(ref mut log) = *self.log.borrow_mut() {
+            log.push(msg);
         }
     }
 }
@@ -1,36 +1,90 @@
-use crate::cpu::CPU;
-use crate::bus::Bus;
-use crate::watcher::Watcher;
-use crate::memory::{MainMemory, ROM, Memory};
-use std::time::{Duration, Instant};
-
-use minifb;
+use crate::display::FrameBuffer;
+use crate::frontend::{Command, Controls, Frontend, Status};
+use crate::input::KeypadState;
+use crate::palette::{mix, Color, Palette};
+use crate::filter::{DisplayFilter, FilterMode};
+
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const LETTERBOX_COLOR: Color = 0x000000;
+
+pub struct WindowOptions {
+    pub palette: Palette,
+    pub filter: FilterMode,
+    pub grid: bool,
+    pub border: usize,
+    pub scale: usize,
+    pub crt: bool,
+}
 
 pub struct Window {
-    cpu: CPU,
-    bus: Bus,
     win: minifb::Window,
     screen_buffer: Vec<u32>,
+    palette: Palette,
+    filter: DisplayFilter,
+    grid: bool,
+    border: usize,
+    crt: bool,
+    title: String,
+}
+
+// Largest integer scale fitting the screen and its border in the window, and
+// the position of the screen once centered.
+fn layout(window: (usize, usize), screen: (usize, usize), border: usize) -> (usize, usize, usize) {
+    let scale = (window.0.saturating_sub(2 * border) / screen.0)
+                    .min(window.1.saturating_sub(2 * border) / screen.1)
+                    .max(1);
+    let left = window.0.saturating_sub(screen.0 * scale) / 2;
+    let top = window.1.saturating_sub(screen.1 * scale) / 2;
 
-    instruction_timestamp: Instant,
-    display_timestamp: Instant,
-    timer_timestamp: Instant,
-    input_timestamp: Instant,
+    (scale, left, top)
 }
 
 impl Window {
-    fn draw_screen(&mut self) {
-        let frame_buffer = self.bus.get_frame_buffer();
+    fn fill_rect(&mut self, rect: (usize, usize, usize, usize), color: Color) {
+        let (window_width, window_height) = self.win.get_size();
+        let (left, top, width, height) = rect;
+
+        for y in top..(top + height).min(window_height) {
+            let row = y * window_width;
+            for pixel in &mut self.screen_buffer[row + left.min(window_width)..row + (left + width).min(window_width)] {
+                *pixel = color;
+            }
+        }
+    }
 
-        for y in 0..320 {
-            let offset = y * 640;
+    fn draw_screen(&mut self, frame_buffer: &FrameBuffer, frames: usize) {
+        let (window_width, window_height) = self.win.get_size();
+        let (width, height) = (frame_buffer.width(), frame_buffer.height());
+        let colors = self.filter.apply(frame_buffer, &self.palette, frames);
+        let (scale, left, top) = layout((window_width, window_height), (width, height), self.border);
+
+        self.screen_buffer.resize(window_width * window_height, 0);
+        self.fill_rect((0, 0, window_width, window_height), LETTERBOX_COLOR);
+        if self.border > 0 {
+            self.fill_rect((left.saturating_sub(self.border), top.saturating_sub(self.border),
+                            width * scale + 2 * self.border, height * scale + 2 * self.border),
+                           self.palette.border_color());
+        }
+
+        for y in 0..(height * scale).min(window_height - top) {
+            let offset = (top + y) * window_width + left;
+            let row = (y / scale) * width;
+            let row_edge = y % scale == 0;
+            // Scanlines need at least two window pixels per screen pixel.
+            let scanline = self.crt && scale > 1 && y % scale == scale - 1;
 
-            for x in 0..640 {
-                let pixel = frame_buffer.read((x / 10, y / 10));
-                self.screen_buffer[offset + x] = if pixel {
-                    0xFFFFFF
+            for x in 0..(width * scale).min(window_width - left) {
+                let color = if self.grid && (row_edge || x % scale == 0) {
+                    self.palette.grid_color()
                 } else {
-                    0x0
+                    colors[row + x / scale]
+                };
+
+                self.screen_buffer[offset + x] = if scanline {
+                    mix(color, LETTERBOX_COLOR, 0.5)
+                } else {
+                    color
                 };
             }
         }
@@ -62,64 +116,93 @@ impl Window {
         }
     }
 
-    fn get_key(&mut self) -> Option<u8> {
-        use minifb::KeyRepeat;
-
-        match self.win.get_keys_pressed(KeyRepeat::Yes) {
-            Some(keys) => if !keys.is_empty() {
-                Window::key_mapping(keys[0])
-            } else {
-                None
-            },
-            None => None,
+    fn get_keys(&self) -> KeypadState {
+        match self.win.get_keys() {
+            Some(keys) => keys.into_iter()
+                              .filter_map(Window::key_mapping)
+                              .fold(0x0, |state, key| state | (1 << key)),
+            None => 0x0,
         }
     }
 
-    pub fn new(rom: ROM, watcher: Watcher) -> Self {
-        let mem = MainMemory::with_rom(rom);
+    fn get_commands(&self) -> Vec<Command> {
+        use minifb::{Key, KeyRepeat};
+
+        let hotkeys = [
+            (Key::P, KeyRepeat::No, Command::TogglePause),
+            (Key::N, KeyRepeat::Yes, Command::Advance),
+            (Key::R, KeyRepeat::No, Command::Reset),
+            (Key::Equal, KeyRepeat::Yes, Command::Faster),
+            (Key::Minus, KeyRepeat::Yes, Command::Slower),
+            (Key::F12, KeyRepeat::No, Command::Screenshot),
+        ];
+
+        hotkeys.iter()
+               .filter(|(key, repeat, _)| self.win.is_key_pressed(*key, *repeat))
+               .map(|(_, _, command)| *command)
+               .collect()
+    }
+
+    pub fn new(options: WindowOptions) -> Self {
+        let scale = options.scale.max(1);
+        let width = LORES_WIDTH * scale + 2 * options.border;
+        let height = LORES_HEIGHT * scale + 2 * options.border;
 
         Window {
-            cpu: CPU::new(watcher),
-            bus: Bus::new(mem),
             win: minifb::Window::new(
                 "Chip8",
-                640,
-                320,
-                minifb::WindowOptions::default()
+                width,
+                height,
+                minifb::WindowOptions {
+                    resize: true,
+                    ..minifb::WindowOptions::default()
+                }
             ).unwrap(),
-            screen_buffer: vec![0; 320 * 640],
-            instruction_timestamp: Instant::now(),
-            display_timestamp: Instant::now(),
-            timer_timestamp: Instant::now(),
-            input_timestamp: Instant::now(),
+            screen_buffer: vec![0; width * height],
+            palette: options.palette,
+            filter: DisplayFilter::new(options.filter),
+            grid: options.grid,
+            border: options.border,
+            crt: options.crt,
+            title: String::new(),
         }
     }
+}
 
-    pub fn run(&mut self) {
-        use minifb::Key::Escape;
+impl Frontend for Window {
+    fn poll(&mut self) -> Controls {
+        use minifb::Key;
 
-        while self.win.is_open() && !self.win.is_key_down(Escape) {
-            let key = self.get_key();
+        Controls {
+            keys: self.get_keys(),
+            commands: self.get_commands(),
+            fast_forward: self.win.is_key_down(Key::Tab),
+            quit: !self.win.is_open() || self.win.is_key_down(Key::Escape),
+        }
+    }
 
-            if key.is_some() || Instant::now() - self.input_timestamp > Duration::from_millis(200) {
-                self.bus.press_key(key);
-                self.input_timestamp = Instant::now();
-            }
+    fn present(&mut self, frame_buffer: &FrameBuffer, status: &Status) {
+        let title = status.to_string();
 
-            if Instant::now() - self.instruction_timestamp > Duration::from_millis(2) {
-                self.cpu.tick(&mut self.bus);
-                self.instruction_timestamp = Instant::now();
-            }
+        if title != self.title {
+            self.win.set_title(&title);
+            self.title = title;
+        }
 
-            if Instant::now() - self.timer_timestamp > Duration::from_millis(17) {
-                self.cpu.timer_decrement();
-                self.timer_timestamp = Instant::now();
-            }
+        self.draw_screen(frame_buffer, status.frames);
+    }
+}
 
-            if Instant::now() - self.display_timestamp > Duration::from_millis(10) {
-                self.draw_screen();
-                self.display_timestamp = Instant::now();
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(layout((640, 320), (64, 32), 0), (10, 0, 0));
+        assert_eq!(layout((640, 320), (128, 64), 0), (5, 0, 0));
+        assert_eq!(layout((660, 340), (64, 32), 10), (10, 10, 10));
+        assert_eq!(layout((800, 320), (64, 32), 0), (10, 80, 0));
+        assert_eq!(layout((100, 20), (128, 64), 0), (1, 0, 0));
     }
 }